@@ -0,0 +1,164 @@
+// Macro-time expansion of `params` directives
+//
+// A directive is an object whose single key is one of these; any other
+// object, even one with a `$`-prefixed key like `$ref`, is plain data:
+//
+// - `{"$repeat": [value, n]}`: a string repeated `n` times, or for any other
+//   value an array of `n` copies, up to 16 MiB in all.
+// - `{"$concat": [a, b, ...]}`: all strings joined, or all arrays appended.
+// - `{"$base64": "..."}`: the decoded bytes as an array of numbers, which is
+//   what a `Vec<u8>` deserializes from.
+// - `{"$file_env": [var, path]}`: only checked here, and left for the runtime
+//   to read when the case runs.
+//
+// Directive arguments may themselves contain directives.
+
+use serde_json::Value;
+
+const DIRECTIVES: [&str; 4] = ["$repeat", "$concat", "$base64", "$file_env"];
+
+// Params are compiled into the test binary; a typo'd count shouldn't exhaust
+// the compiler's memory
+const MAX_REPEAT_BYTES: usize = 16 << 20;
+
+pub(crate) fn expand(value: Value) -> Result<Value, String> {
+    match value {
+        Value::Object(map) => {
+            let directive = match map.iter().next() {
+                Some((key, _)) if map.len() == 1 && DIRECTIVES.contains(&key.as_str()) => {
+                    key.clone()
+                }
+                _ => {
+                    return map
+                        .into_iter()
+                        .map(|(key, value)| Ok((key, expand(value)?)))
+                        .collect::<Result<_, String>>()
+                        .map(Value::Object)
+                }
+            };
+            let args = expand(map.into_iter().next().expect("checked above").1)?;
+            match directive.as_str() {
+                "$repeat" => repeat(args),
+                "$concat" => concat(args),
+                "$base64" => base64(args),
+                _ => file_env(args),
+            }
+        }
+        Value::Array(items) => items
+            .into_iter()
+            .map(expand)
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+        other => Ok(other),
+    }
+}
+
+fn repeat(args: Value) -> Result<Value, String> {
+    let usage = || "`$repeat` expects `[value, count]`".to_string();
+    let Value::Array(args) = args else {
+        return Err(usage());
+    };
+    let [value, count] = <[Value; 2]>::try_from(args).map_err(|_| usage())?;
+    let count = count
+        .as_u64()
+        .and_then(|count| usize::try_from(count).ok())
+        .ok_or_else(usage)?;
+    let size = match &value {
+        Value::String(s) => s.len(),
+        value => value.to_string().len(),
+    };
+    if count.saturating_mul(size.max(1)) > MAX_REPEAT_BYTES {
+        return Err(format!(
+            "`$repeat` of {} copies would expand past {} MiB",
+            count,
+            MAX_REPEAT_BYTES >> 20
+        ));
+    }
+    Ok(match value {
+        Value::String(s) => Value::String(s.repeat(count)),
+        value => Value::Array(vec![value; count]),
+    })
+}
+
+fn concat(args: Value) -> Result<Value, String> {
+    let usage = || "`$concat` expects an array of all strings or all arrays".to_string();
+    let Value::Array(args) = args else {
+        return Err(usage());
+    };
+    if args.iter().all(Value::is_string) {
+        Ok(Value::String(
+            args.iter().filter_map(Value::as_str).collect::<String>(),
+        ))
+    } else if args.iter().all(Value::is_array) {
+        Ok(Value::Array(
+            args.into_iter()
+                .flat_map(|arg| match arg {
+                    Value::Array(items) => items,
+                    _ => unreachable!("checked above"),
+                })
+                .collect(),
+        ))
+    } else {
+        Err(usage())
+    }
+}
+
+fn file_env(args: Value) -> Result<Value, String> {
+    match &args {
+        Value::Array(items) if items.len() == 2 && items.iter().all(Value::is_string) => {
+            Ok(serde_json::json!({ "$file_env": args }))
+        }
+        _ => Err("`$file_env` expects `[env_var, path]`".to_string()),
+    }
+}
+
+fn base64(args: Value) -> Result<Value, String> {
+    let Value::String(encoded) = args else {
+        return Err("`$base64` expects a string".to_string());
+    };
+    let mut bytes = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in encoded.trim_end_matches('=').chars() {
+        let sextet = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            _ => return Err(format!("`$base64`: invalid character {:?}", c)),
+        };
+        buffer = (buffer << 6) | sextet;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push(Value::from((buffer >> bits) as u8));
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bits >= 6 {
+        return Err("`$base64`: truncated input".to_string());
+    }
+    Ok(Value::Array(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn only_known_directives_expand() {
+        let params = json!({ "$ref": "#/a", "s": { "$repeat": ["ab", 2] } });
+        assert_eq!(expand(params), Ok(json!({ "$ref": "#/a", "s": "abab" })));
+    }
+
+    #[test]
+    fn repeats_are_capped() {
+        let error = expand(json!({ "$repeat": ["x", 1e9 as u64] })).unwrap_err();
+        assert_eq!(
+            error,
+            "`$repeat` of 1000000000 copies would expand past 16 MiB"
+        );
+    }
+}
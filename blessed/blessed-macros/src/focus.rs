@@ -0,0 +1,28 @@
+// A committed `blessed.focus` next to `Cargo.toml` narrows a run to the
+// cases it lists, one name per line; `#` starts a comment. The other cases'
+// tests are generated `#[ignore]`d, so `cargo test -- --ignored` still runs
+// them. Without the file every case runs.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+pub(crate) const FOCUS_FILE: &str = "blessed.focus";
+
+// The focus file and the case names it lists, if there is one
+pub(crate) fn read_focus(
+    manifest_dir: &Path,
+) -> Result<Option<(PathBuf, BTreeSet<String>)>, String> {
+    let path = manifest_dir.join(FOCUS_FILE);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", FOCUS_FILE, e)),
+    };
+    let names = contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    Ok(Some((path, names)))
+}
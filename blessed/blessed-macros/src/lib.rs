@@ -1,18 +1,106 @@
-use glob;
 use proc_macro::TokenStream;
 use quote::quote;
 use serde::Deserialize;
 use serde_json::{self, Value as JsonValue};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use syn::{parse_macro_input, punctuated::Punctuated, Ident, ItemFn, LitStr, PatType, Token};
+use syn::{parse_macro_input, Ident, ItemFn, PatType};
 
-#[derive(Deserialize, Debug)]
+mod directives;
+mod focus;
+mod options;
+
+use options::{HarnessOptions, OptionList, TestsOptions};
+
+#[derive(Deserialize, Debug, Clone)]
+// A misspelled key would otherwise be ignored, e.g. running the harness
+// without the intended `params`
+#[serde(deny_unknown_fields)]
 struct BlessedDefinition {
+    // Empty for a `compare_harnesses` case
+    #[serde(default)]
     harness: String,
-    params: JsonValue,
+    // `null` counts as present, so it conflicts with `text_dir` and the like;
+    // when no source of params is given, they default to `null`
+    #[serde(default, deserialize_with = "present")]
+    params: Option<JsonValue>,
+    // Directory (relative to the definition file) whose files each become a
+    // case, passed verbatim to the harness as a `String`
+    #[serde(default)]
+    text_dir: Option<String>,
+    // Parameter lists whose Cartesian product becomes one case per
+    // combination, each getting an object of one value per dimension as params
+    #[serde(default)]
+    matrix: Option<serde_json::Map<String, JsonValue>>,
+    // Pattern selecting files inside `text_dir`; defaults to every file
+    #[serde(default)]
+    glob: Option<String>,
+    // Platform predicate such as `cfg(windows)`; the case only exists where it holds
+    #[serde(default)]
+    when: Option<String>,
+    // Numbers within this distance of the committed snapshot's still match
+    #[serde(default)]
+    float_epsilon: Option<f64>,
+    // Rules replacing volatile output values; passed through to the runtime
+    #[serde(default)]
+    redact: Vec<JsonValue>,
+    // JSON pointer selecting the part of the output to snapshot
+    #[serde(default)]
+    project: Option<String>,
+    // Directory (relative to the definition file) the harness resolves
+    // relative paths against, via `blessed::case_path`
+    #[serde(default)]
+    cwd: Option<String>,
+    // Acknowledges the snapshot is expected to be new, so it being untracked passes
+    #[serde(default)]
+    new: bool,
+    // Top-level output keys left out when comparing against the committed snapshot
+    #[serde(default)]
+    ignore_keys: Vec<String>,
+    // `{"generator": ..., "count": ..., "seed": ...}`: run the harness over
+    // generated inputs and snapshot the distribution of output shapes
+    #[serde(default)]
+    fuzz: Option<FuzzDefinition>,
+    // Made available to the harness as `blessed::current_seed()`
+    #[serde(default)]
+    seed: Option<u64>,
+    // Known-flaky: failures are reported but don't fail the test
+    #[serde(default)]
+    quarantine: bool,
+    // Changes only to trailing whitespace or blank lines don't fail the case
+    #[serde(default)]
+    loose_whitespace: bool,
+    // Changes only to letter case don't fail the case
+    #[serde(default)]
+    ignore_case: bool,
+    // Snapshot to `{case}.{os}.json`, falling back to a committed `{case}.json`
+    #[serde(default)]
+    per_os: bool,
+    // The snapshot inline: the output is compared against it directly,
+    // without a file under `blessed/` or git
+    #[serde(default, deserialize_with = "present")]
+    expected: Option<JsonValue>,
+    // Instead of `harness`: runs each of these on the params and fails unless
+    // they all produce the same snapshot, without writing one
+    #[serde(default)]
+    compare_harnesses: Vec<String>,
+}
+
+// Validated here so a missing seed fails the build; passed through to the runtime
+#[derive(Deserialize, serde::Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct FuzzDefinition {
+    generator: String,
+    count: u32,
+    seed: u64,
+}
+
+fn present<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<JsonValue>, D::Error> {
+    JsonValue::deserialize(deserializer).map(Some)
 }
 
 // Intermediate struct to hold processed test information
@@ -20,36 +108,96 @@ struct BlessedDefinition {
 struct PreparedTest {
     test_fn_name: Ident,
     test_name: String,
+    definition_file_rel_str: String,
     harness_name: String,
     params: JsonValue,
     output_file_path_rel_str: String,
+    // Set when `tests!(compact_copies)` also writes `{name}.min.json`
+    compact_copy: bool,
+    cfg_attr: Option<syn::Meta>,
+    float_epsilon: Option<f64>,
+    // Settings applied at runtime, as `blessed::CaseOptions` JSON
+    case_options: JsonValue,
+    // Set when a `blessed.focus` file exists and doesn't list the case
+    unfocused: bool,
 }
 
 // Struct to hold common paths
 struct ProjectPaths {
-    git_root: PathBuf,
-    git_root_str: String,
-    output_dir_abs: PathBuf,
+    manifest_dir: PathBuf,
+    // The crate directory relative to the git root. Generated tests locate
+    // both from `CARGO_MANIFEST_DIR` rather than baking in this machine's
+    // absolute paths
+    manifest_rel: PathBuf,
     glob_pattern_str: String,
 }
 
 #[proc_macro_attribute]
-pub fn harness(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn harness(attr: TokenStream, item: TokenStream) -> TokenStream {
     // TODO: Write test case for every panic here
 
+    let args = parse_macro_input!(attr with OptionList::parse_terminated);
+    let options = match HarnessOptions::parse(args) {
+        Ok(options) => options,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let allow_unused = options.allow_unused;
+    let debug = options.debug;
+    let text = options.text;
+    let trailing_newline = options.trailing_newline.unwrap_or(true);
+    let normalize_newlines = options.normalize_newlines;
+    let canonical_numbers = options.canonical_numbers;
+    let sensitive = options.sensitive;
+    let keyed = options.keyed;
+    let format_version = match options.format_version {
+        Some(version) => quote! { Some(#version) },
+        None => quote! { None },
+    };
+
     let func = parse_macro_input!(item as ItemFn);
     let func_name = &func.sig.ident;
     let func_name_str = func_name.to_string();
+    let description = options.desc.unwrap_or_else(|| doc_summary(&func.attrs));
 
-    // Extract input argument type
-    let input_arg = func
-        .sig
-        .inputs
-        .first()
-        .expect("Harness function must have exactly one argument");
-    let input_type = match input_arg {
-        syn::FnArg::Typed(PatType { ty, .. }) => ty,
-        _ => panic!("Harness function argument must be typed"),
+    // Extract input argument type; harnesses may also take no input at all
+    if func.sig.inputs.len() > 1 {
+        return syn::Error::new_spanned(
+            &func.sig.inputs,
+            "Harness function must take at most one argument",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let takes_params = !func.sig.inputs.is_empty();
+    let input_type = match func.sig.inputs.first() {
+        Some(syn::FnArg::Typed(PatType { ty, .. })) => {
+            let name = type_name(ty);
+            quote! { Some(#name) }
+        }
+        _ => quote! { None },
+    };
+    if options.raw_input && !takes_params {
+        return syn::Error::new_spanned(
+            &func.sig,
+            "A `raw_input` harness must take a `serde_json::Value` argument",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let call = match func.sig.inputs.first() {
+        Some(syn::FnArg::Typed(_)) if options.raw_input => quote! {
+            #func_name(input_json)
+        },
+        Some(syn::FnArg::Typed(PatType { ty, .. })) => quote! {
+            let input: #ty = ::serde_json::from_value(input_json)
+                .map_err(|e| format!("Failed to deserialize input: {}", e))?;
+            #func_name(input)
+        },
+        Some(_) => panic!("Harness function argument must be typed"),
+        None => quote! {
+            let _ = input_json; // null; checked by the runtime
+            #func_name()
+        },
     };
 
     // Extract return type
@@ -58,6 +206,46 @@ pub fn harness(_attr: TokenStream, item: TokenStream) -> TokenStream {
         _ => panic!("Harness function must have a return type"),
     };
 
+    let output_type_name = type_name(output_type);
+
+    let (serialize_output, format) = if debug {
+        (
+            quote! { Ok(::serde_json::Value::String(format!("{:#?}", output))) },
+            quote! { ::blessed::SnapshotFormat::Text },
+        )
+    } else if text {
+        (
+            quote! { Ok(::serde_json::Value::String(::std::string::String::from(output))) },
+            quote! { ::blessed::SnapshotFormat::Text },
+        )
+    } else {
+        let format = if options.ndjson {
+            quote! { ::blessed::SnapshotFormat::Ndjson }
+        } else {
+            quote! { ::blessed::SnapshotFormat::Json }
+        };
+        (
+            quote! {
+                ::blessed::serialize_output(output)
+            },
+            format,
+        )
+    };
+
+    // A violation is a bug in the harness's serializer, so it fails the test
+    // rather than being snapshotted
+    let serialize_output = if options.schema {
+        quote! {
+            let output = { #serialize_output }?;
+            if let Err(e) = ::blessed::validate_output::<#output_type>(&output) {
+                panic!("{}", e);
+            }
+            Ok(output)
+        }
+    } else {
+        serialize_output
+    };
+
     // Generate the wrapper function name
     let wrapper_func_name = Ident::new(
         &format!("__blessed_harness_{}", func_name),
@@ -69,17 +257,191 @@ pub fn harness(_attr: TokenStream, item: TokenStream) -> TokenStream {
 
         #[doc(hidden)]
         fn #wrapper_func_name(input_json: ::serde_json::Value) -> Result<::serde_json::Value, String> {
-            let input: #input_type = ::serde_json::from_value(input_json)
-                .map_err(|e| format!("Failed to deserialize input: {}", e))?;
+            let output: #output_type = { #call };
 
-            let output: #output_type = #func_name(input);
-
-            ::serde_json::to_value(output)
-                .map_err(|e| format!("Failed to serialize output: {}", e))
+            #serialize_output
         }
 
         ::inventory::submit! {
             ::blessed::HarnessFn {
+                name: #func_name_str,
+                module_path: module_path!(),
+                func: #wrapper_func_name,
+                allow_unused: #allow_unused,
+                description: #description,
+                format: #format,
+                format_version: #format_version,
+                trailing_newline: #trailing_newline,
+                normalize_newlines: #normalize_newlines,
+                canonical_numbers: #canonical_numbers,
+                sensitive: #sensitive,
+                keyed: #keyed,
+                takes_params: #takes_params,
+                input_type: #input_type,
+                output_type: #output_type_name,
+            }
+        }
+    };
+
+    TokenStream::from(generated_code)
+}
+
+// First paragraph of a doc comment, joined into one line
+fn doc_summary(attrs: &[syn::Attribute]) -> String {
+    let lines = attrs.iter().filter_map(|attr| match &attr.meta {
+        syn::Meta::NameValue(nv) if nv.path.is_ident("doc") => match &nv.value {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(s),
+                ..
+            }) => Some(s.value()),
+            _ => None,
+        },
+        _ => None,
+    });
+    lines
+        .map(|line| line.trim().to_string())
+        .skip_while(|line| line.is_empty())
+        .take_while(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[proc_macro_attribute]
+pub fn params_hook(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+    let func_name = &func.sig.ident;
+
+    if func.sig.inputs.len() != 1 || !matches!(func.sig.output, syn::ReturnType::Default) {
+        return syn::Error::new_spanned(
+            &func.sig,
+            "Params hook must have the signature `fn(&mut serde_json::Value)`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let generated_code = quote! {
+        #func
+
+        ::inventory::submit! {
+            ::blessed::ParamsHook {
+                func: #func_name,
+            }
+        }
+    };
+
+    TokenStream::from(generated_code)
+}
+
+/// Registers a function rewriting the text of every snapshot file just
+/// before it is written: `fn(String) -> String`.
+#[proc_macro_attribute]
+pub fn output_hook(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+    let func_name = &func.sig.ident;
+
+    if func.sig.inputs.len() != 1 || matches!(func.sig.output, syn::ReturnType::Default) {
+        return syn::Error::new_spanned(
+            &func.sig,
+            "Output hook must have the signature `fn(String) -> String`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let generated_code = quote! {
+        #func
+
+        ::inventory::submit! {
+            ::blessed::OutputHook {
+                func: #func_name,
+            }
+        }
+    };
+
+    TokenStream::from(generated_code)
+}
+
+/// Registers a function building a shared context, called once, the first
+/// time a harness asks for its return type with `blessed::context::<T>()`.
+#[proc_macro_attribute]
+pub fn context(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+    let func_name = &func.sig.ident;
+
+    let context_type = match &func.sig.output {
+        syn::ReturnType::Type(_, ty) if func.sig.inputs.is_empty() => ty,
+        _ => return syn::Error::new_spanned(
+            &func.sig,
+            "Context function must have the signature `fn() -> T` where `T: Send + Sync + 'static`",
+        )
+        .to_compile_error()
+        .into(),
+    };
+    let type_id_func_name = Ident::new(
+        &format!("__blessed_context_type_{}", func_name),
+        func_name.span(),
+    );
+    let init_func_name = Ident::new(
+        &format!("__blessed_context_init_{}", func_name),
+        func_name.span(),
+    );
+
+    let generated_code = quote! {
+        #func
+
+        #[doc(hidden)]
+        fn #type_id_func_name() -> ::std::any::TypeId {
+            ::std::any::TypeId::of::<#context_type>()
+        }
+
+        #[doc(hidden)]
+        fn #init_func_name() -> Box<dyn ::std::any::Any + Send + Sync> {
+            Box::new(#func_name())
+        }
+
+        ::inventory::submit! {
+            ::blessed::ContextFn {
+                type_id: #type_id_func_name,
+                init: #init_func_name,
+            }
+        }
+    };
+
+    TokenStream::from(generated_code)
+}
+
+#[proc_macro_attribute]
+pub fn generator(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+    let func_name = &func.sig.ident;
+    let func_name_str = func_name.to_string();
+
+    if func.sig.inputs.len() != 1 || matches!(func.sig.output, syn::ReturnType::Default) {
+        return syn::Error::new_spanned(
+            &func.sig,
+            "Generator must have the signature `fn(&mut blessed::Rng) -> T` where `T: Serialize`",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let wrapper_func_name = Ident::new(
+        &format!("__blessed_generator_{}", func_name),
+        func_name.span(),
+    );
+
+    let generated_code = quote! {
+        #func
+
+        #[doc(hidden)]
+        fn #wrapper_func_name(rng: &mut ::blessed::Rng) -> Result<::serde_json::Value, String> {
+            ::serde_json::to_value(#func_name(rng))
+                .map_err(|e| format!("Failed to serialize generated params: {}", e))
+        }
+
+        ::inventory::submit! {
+            ::blessed::GeneratorFn {
                 name: #func_name_str,
                 func: #wrapper_func_name,
             }
@@ -134,18 +496,41 @@ fn find_project_paths() -> Result<ProjectPaths, syn::Error> {
     if !git_root.is_absolute() {
         return Err(syn::Error::new(proc_macro2::Span::call_site(), format!("Determined git root path is not absolute: {:?}. Blessed requires an absolute path.", git_root)));
     }
-    let git_root_str_final = git_root
-        .to_str()
-        .ok_or_else(|| {
+    if git_root.to_str().is_none() {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            format!("Git root path is not valid UTF-8: {:?}", git_root),
+        ));
+    }
+    // git resolves symlinks (e.g. macOS's `/var` -> `/private/var`) while
+    // CARGO_MANIFEST_DIR may not, so compare canonical forms
+    let canonical = |path: &Path| {
+        fs::canonicalize(path).map_err(|e| {
             syn::Error::new(
                 proc_macro2::Span::call_site(),
-                format!("Git root path is not valid UTF-8: {:?}", git_root),
+                format!("Failed to canonicalize {:?}: {}", path, e),
+            )
+        })
+    };
+    let manifest_rel = canonical(&manifest_dir)?
+        .strip_prefix(canonical(&git_root)?)
+        .map_err(|_| {
+            syn::Error::new(
+                proc_macro2::Span::call_site(),
+                format!(
+                    "Crate directory {:?} is not inside git root {:?}",
+                    manifest_dir, git_root
+                ),
             )
         })?
-        .to_string();
+        .to_path_buf();
 
-    let output_dir_abs = manifest_dir.join("blessed/");
-    let absolute_glob_pattern = manifest_dir.join("src/**/*.blessed.json");
+    // BLESSED_ROOT (absolute, or relative to the crate) moves fixtures out of `src/`
+    let definitions_root = match std::env::var_os("BLESSED_ROOT") {
+        Some(root) if !root.is_empty() => manifest_dir.join(root),
+        _ => manifest_dir.join("src"),
+    };
+    let absolute_glob_pattern = definitions_root.join("**/*.blessed.json");
     let glob_pattern_str = absolute_glob_pattern
         .to_str()
         .ok_or_else(|| {
@@ -160,17 +545,29 @@ fn find_project_paths() -> Result<ProjectPaths, syn::Error> {
         .to_string();
 
     Ok(ProjectPaths {
-        git_root,
-        git_root_str: git_root_str_final,
-        output_dir_abs,
+        manifest_dir,
+        manifest_rel,
         glob_pattern_str,
     })
 }
 
-// Helper function to collect test definitions from files
-fn collect_test_definitions(paths: &ProjectPaths) -> Result<(Vec<PreparedTest>, bool), syn::Error> {
+// Everything found under the definitions root
+struct CollectedDefinitions {
+    prepared_tests: Vec<PreparedTest>,
+    definition_files: Vec<PathBuf>,
+    // `file: error` for each file or case that failed to parse
+    parse_errors: Vec<String>,
+}
+
+// Helper function to collect test definitions from files. Files that fail to
+// parse are reported together rather than aborting on the first
+fn collect_test_definitions(
+    paths: &ProjectPaths,
+    options: &TestsOptions,
+) -> Result<CollectedDefinitions, syn::Error> {
     let mut prepared_tests = Vec::new();
-    let mut found_files = false;
+    let mut definition_files = Vec::new();
+    let mut parse_errors = Vec::new();
 
     eprintln!(
         "Searching for blessed files using glob: {}",
@@ -185,13 +582,13 @@ fn collect_test_definitions(paths: &ProjectPaths) -> Result<(Vec<PreparedTest>,
                         if !input_json_path.is_file() {
                             continue;
                         }
-                        found_files = true;
+                        definition_files.push(input_json_path.clone());
                         eprintln!("Processing blessed definition file: {:?}", input_json_path);
 
-                        let file_stem = input_json_path
-                            .file_stem()
+                        // `tests.blessed.json` contributes `tests` to the generated test names
+                        let definition_file_name = input_json_path
+                            .file_name()
                             .and_then(|s| s.to_str())
-                            .map(|stem| stem.replace(|c: char| !c.is_alphanumeric(), "_"))
                             .ok_or_else(|| {
                                 syn::Error::new(
                                     proc_macro2::Span::call_site(),
@@ -202,6 +599,12 @@ fn collect_test_definitions(paths: &ProjectPaths) -> Result<(Vec<PreparedTest>,
                                 )
                             })?;
 
+                        let definition_file_rel_str = input_json_path
+                            .strip_prefix(&paths.manifest_dir)
+                            .unwrap_or(&input_json_path)
+                            .to_string_lossy()
+                            .to_string();
+
                         let file_content = fs::read_to_string(&input_json_path).map_err(|e| {
                             syn::Error::new(
                                 proc_macro2::Span::call_site(),
@@ -210,58 +613,52 @@ fn collect_test_definitions(paths: &ProjectPaths) -> Result<(Vec<PreparedTest>,
                         })?;
 
                         // TODO: Implement advanced test authoring features here by processing the raw cases
-                        let test_cases: HashMap<String, BlessedDefinition> =
-                            serde_json::from_str(&file_content).map_err(|e| {
-                                syn::Error::new(
-                                    proc_macro2::Span::call_site(),
-                                    format!(
-                                        "Failed to parse blessed file {:?}: {}",
-                                        input_json_path, e
-                                    ),
-                                )
-                            })?;
-
-                        for (test_name, definition) in test_cases {
-                            let test_fn_name = Ident::new(
-                                &format!("blessed_test_{}_{}", file_stem, test_name),
+                        let mut raw_cases: serde_json::Map<String, JsonValue> =
+                            match serde_json::from_str(&file_content) {
+                                Ok(raw_cases) => raw_cases,
+                                Err(e) => {
+                                    parse_errors
+                                        .push(format!("{}: {}", definition_file_rel_str, e));
+                                    continue;
+                                }
+                            };
+                        apply_default_harness(&mut raw_cases, &definition_file_rel_str)?;
+                        // `{}` is more likely an accidentally emptied fixture than intended;
+                        // cases that fail to parse are reported as such instead
+                        if raw_cases.is_empty() && !options.allow_empty_files {
+                            return Err(syn::Error::new(
                                 proc_macro2::Span::call_site(),
-                            );
-                            let output_file_name = format!("{}.json", test_name);
-                            let output_file_path_abs = paths.output_dir_abs.join(&output_file_name);
-
-                            let output_file_path_rel = output_file_path_abs
-                                .strip_prefix(&paths.git_root)
-                                .map_err(|_| {
-                                    syn::Error::new(
-                                        test_fn_name.span(),
-                                        format!(
-                                            "Output file path {:?} is not inside git root {:?}",
-                                            output_file_path_abs, paths.git_root
-                                        ),
-                                    )
-                                })?
-                                .to_path_buf();
-
-                            let output_file_path_rel_str = output_file_path_rel
-                                .to_str()
-                                .ok_or_else(|| {
-                                    syn::Error::new(
-                                        test_fn_name.span(),
-                                        format!(
-                                            "Relative output path is not valid UTF-8: {:?}",
-                                            output_file_path_rel
-                                        ),
-                                    )
-                                })?
-                                .to_string();
-
-                            prepared_tests.push(PreparedTest {
-                                test_fn_name,
-                                test_name: test_name.clone(),
-                                harness_name: definition.harness,
-                                params: definition.params,
-                                output_file_path_rel_str,
-                            });
+                                format!(
+                                    "Blessed file {} defines no cases; add a case, delete it, or use `tests!(allow_empty_files)`",
+                                    definition_file_rel_str
+                                ),
+                            ));
+                        }
+                        let mut test_cases = HashMap::new();
+                        for (case_name, raw_case) in raw_cases {
+                            match serde_json::from_value::<BlessedDefinition>(raw_case) {
+                                Ok(definition) => {
+                                    test_cases.insert(case_name, definition);
+                                }
+                                Err(e) => parse_errors.push(format!(
+                                    "{}: case '{}': {}",
+                                    definition_file_rel_str, case_name, e
+                                )),
+                            }
+                        }
+                        for (case_name, definition) in test_cases {
+                            for (test_name, definition) in
+                                expand_case(&input_json_path, case_name, definition)?
+                            {
+                                prepared_tests.push(prepare_test(
+                                    paths,
+                                    options,
+                                    definition_file_name,
+                                    &definition_file_rel_str,
+                                    test_name,
+                                    definition,
+                                )?);
+                            }
                         }
                     }
                     Err(e) => {
@@ -284,119 +681,607 @@ fn collect_test_definitions(paths: &ProjectPaths) -> Result<(Vec<PreparedTest>,
         }
     }
 
-    Ok((prepared_tests, found_files))
+    parse_errors.sort();
+    Ok(CollectedDefinitions {
+        prepared_tests,
+        definition_files,
+        parse_errors,
+    })
+}
+
+// A top-level `"_harness": "name"` is the harness for every case that doesn't
+// name its own; a case with neither is an error
+fn apply_default_harness(
+    raw_cases: &mut serde_json::Map<String, JsonValue>,
+    definition_file_rel_str: &str,
+) -> Result<(), syn::Error> {
+    let error = |message: String| syn::Error::new(proc_macro2::Span::call_site(), message);
+    let default_harness = match raw_cases.remove("_harness") {
+        None => None,
+        Some(JsonValue::String(harness)) => Some(harness),
+        Some(other) => {
+            return Err(error(format!(
+                "`_harness` in {} must be a harness name, found {}",
+                definition_file_rel_str, other
+            )))
+        }
+    };
+    for (case_name, raw_case) in raw_cases.iter_mut() {
+        let Some(raw_case) = raw_case.as_object_mut() else {
+            continue; // reported when the case is parsed
+        };
+        if raw_case.contains_key("harness") || raw_case.contains_key("compare_harnesses") {
+            continue;
+        }
+        match &default_harness {
+            Some(harness) => {
+                raw_case.insert("harness".to_string(), harness.clone().into());
+            }
+            None => {
+                return Err(error(format!(
+                    "Case '{}' in {} has no `harness`, and the file sets no `_harness` default",
+                    case_name, definition_file_rel_str
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+// Expands a definition that stands for several cases (e.g. a `text_dir`) into
+// one named definition per case; ordinary definitions pass through unchanged
+fn expand_case(
+    definition_path: &std::path::Path,
+    case_name: String,
+    definition: BlessedDefinition,
+) -> Result<Vec<(String, BlessedDefinition)>, syn::Error> {
+    let error = |msg: String| {
+        syn::Error::new(
+            proc_macro2::Span::call_site(),
+            format!("Case '{}' in {:?}: {}", case_name, definition_path, msg),
+        )
+    };
+
+    if definition.fuzz.is_some() {
+        if definition.params.is_some()
+            || definition.text_dir.is_some()
+            || definition.matrix.is_some()
+        {
+            return Err(error(
+                "`fuzz` generates the params, so it can't be combined with `params`, `text_dir` or `matrix`"
+                    .to_string(),
+            ));
+        }
+        return Ok(vec![(case_name, definition)]);
+    }
+
+    if let Some(matrix) = &definition.matrix {
+        if definition.params.is_some() || definition.text_dir.is_some() {
+            return Err(error(
+                "`matrix` generates the params, so it can't be combined with `params` or `text_dir`"
+                    .to_string(),
+            ));
+        }
+        let mut expanded = Vec::new();
+        for (suffix, params) in expand_matrix(matrix).map_err(error)? {
+            let mut case = definition.clone();
+            case.params = Some(params);
+            case.matrix = None;
+            expanded.push((format!("{}_{}", case_name, suffix), case));
+        }
+        return Ok(expanded);
+    }
+
+    // A missing `params` is `null`, for harnesses that take none
+    let Some(text_dir) = &definition.text_dir else {
+        return Ok(vec![(case_name, definition)]);
+    };
+    if definition.params.is_some() {
+        return Err(error(
+            "`params` and `text_dir` are mutually exclusive".to_string(),
+        ));
+    }
+
+    let text_dir = definition_path
+        .parent()
+        .unwrap_or(std::path::Path::new(""))
+        .join(text_dir);
+    let pattern = text_dir.join(definition.glob.as_deref().unwrap_or("*"));
+    let pattern = pattern
+        .to_str()
+        .ok_or_else(|| error(format!("`text_dir` path is not valid UTF-8: {:?}", pattern)))?;
+    let entries = glob::glob(pattern)
+        .map_err(|e| error(format!("invalid `glob` pattern '{}': {}", pattern, e)))?;
+
+    let mut expanded = Vec::new();
+    for entry in entries {
+        let text_path = entry.map_err(|e| error(format!("error reading `text_dir`: {}", e)))?;
+        if !text_path.is_file() {
+            continue;
+        }
+        let stem = text_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| {
+                error(format!(
+                    "could not get file stem from path: {:?}",
+                    text_path
+                ))
+            })?;
+        let text = fs::read_to_string(&text_path)
+            .map_err(|e| error(format!("failed to read {:?}: {}", text_path, e)))?;
+
+        let mut case = definition.clone();
+        case.params = Some(JsonValue::String(text));
+        case.text_dir = None;
+        case.glob = None;
+        expanded.push((format!("{}_{}", case_name, stem), case));
+    }
+    if expanded.is_empty() {
+        return Err(error(format!("no files match '{}'", pattern)));
+    }
+    Ok(expanded)
+}
+
+// Every combination of one value per dimension, as a name suffix encoding its
+// coordinates (`grammar_json_input_2`) and the params object. A dimension
+// whose values are all short, distinct plain words names them by value;
+// otherwise every value in it is named by its index, so names never clash
+fn expand_matrix(
+    matrix: &serde_json::Map<String, JsonValue>,
+) -> Result<Vec<(String, JsonValue)>, String> {
+    if matrix.is_empty() {
+        return Err("`matrix` has no dimensions".to_string());
+    }
+    let mut combinations = vec![(Vec::new(), serde_json::Map::new())];
+    for (dimension, values) in matrix {
+        let values = match values {
+            JsonValue::Array(values) if !values.is_empty() => values,
+            _ => {
+                return Err(format!(
+                    "`matrix` dimension '{}' must be a non-empty array",
+                    dimension
+                ))
+            }
+        };
+        if let Some((index, value)) = values
+            .iter()
+            .enumerate()
+            .find(|(index, value)| values[..*index].contains(value))
+        {
+            return Err(format!(
+                "`matrix` dimension '{}' lists {} twice (at index {})",
+                dimension, value, index
+            ));
+        }
+        let named: Option<Vec<String>> = values.iter().map(plain_coordinate).collect();
+        let suffixes = match named {
+            Some(named)
+                if named
+                    .iter()
+                    .enumerate()
+                    .all(|(i, name)| !named[..i].contains(name)) =>
+            {
+                named
+            }
+            _ => (0..values.len()).map(|index| index.to_string()).collect(),
+        };
+        let mut next = Vec::new();
+        for (coordinates, params) in &combinations {
+            for (value, suffix) in values.iter().zip(&suffixes) {
+                let mut coordinates: Vec<String> = coordinates.clone();
+                coordinates.push(format!("{}_{}", dimension, suffix));
+                let mut params = params.clone();
+                params.insert(dimension.clone(), value.clone());
+                next.push((coordinates, params));
+            }
+        }
+        combinations = next;
+    }
+
+    let mut names = std::collections::HashSet::new();
+    combinations
+        .into_iter()
+        .map(|(coordinates, params)| {
+            let name = coordinates.join("_");
+            if !names.insert(name.clone()) {
+                return Err(format!(
+                    "`matrix` produces the case name suffix '{}' twice; rename a dimension",
+                    name
+                ));
+            }
+            Ok((name, JsonValue::Object(params)))
+        })
+        .collect()
+}
+
+// The name a matrix value gives itself, if it is a short, plain word
+fn plain_coordinate(value: &JsonValue) -> Option<String> {
+    let text = match value {
+        JsonValue::String(s) => s.clone(),
+        JsonValue::Number(n) => n.to_string(),
+        JsonValue::Bool(b) => b.to_string(),
+        _ => return None,
+    };
+    let plain = !text.is_empty()
+        && text.len() <= 24
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    plain.then_some(text)
+}
+
+// Helper function to turn one case of a definition file into a PreparedTest
+fn prepare_test(
+    paths: &ProjectPaths,
+    options: &TestsOptions,
+    definition_file_name: &str,
+    definition_file_rel_str: &str,
+    test_name: String,
+    mut definition: BlessedDefinition,
+) -> Result<PreparedTest, syn::Error> {
+    let test_fn_name = Ident::new(
+        &blessed_naming::test_fn_name(definition_file_name, &test_name),
+        proc_macro2::Span::call_site(),
+    );
+
+    let params = directives::expand(definition.params.unwrap_or_default()).map_err(|e| {
+        syn::Error::new(
+            proc_macro2::Span::call_site(),
+            format!("Case '{}' in {}: {}", test_name, definition_file_rel_str, e),
+        )
+    })?;
+
+    let cfg_attr = definition
+        .when
+        .as_deref()
+        .map(|when| parse_when(when, definition_file_rel_str, &test_name))
+        .transpose()?;
+
+    let cwd = definition
+        .cwd
+        .as_deref()
+        .map(|cwd| resolve_cwd(paths, cwd, definition_file_rel_str, &test_name))
+        .transpose()?;
+
+    let case_error = |message: &str| {
+        syn::Error::new(
+            proc_macro2::Span::call_site(),
+            format!(
+                "Case '{}' in {}: {}",
+                test_name, definition_file_rel_str, message
+            ),
+        )
+    };
+    if !definition.compare_harnesses.is_empty() {
+        if !definition.harness.is_empty() {
+            return Err(case_error(
+                "`harness` and `compare_harnesses` can't be combined",
+            ));
+        }
+        if definition.compare_harnesses.len() < 2 {
+            return Err(case_error(
+                "`compare_harnesses` needs at least two harnesses",
+            ));
+        }
+        if definition.expected.is_some() || definition.new {
+            return Err(case_error(
+                "a `compare_harnesses` case has no snapshot, so `expected` and `new` don't apply",
+            ));
+        }
+        definition.harness = definition.compare_harnesses[0].clone();
+    } else if definition.harness.is_empty() {
+        return Err(case_error("no `harness` given"));
+    }
+
+    if definition.expected.is_some() && definition.new {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            format!(
+                "Case '{}' in {}: `new` has no effect on a case with `expected`",
+                test_name, definition_file_rel_str
+            ),
+        ));
+    }
+
+    // `::` in a qualified harness name isn't valid in Windows file names
+    let output_file_name = if options.per_harness_dirs {
+        format!(
+            "{}/{}.json",
+            definition.harness.replace("::", "__"),
+            test_name
+        )
+    } else if options.harness_prefixed_names {
+        format!(
+            "{}__{}.json",
+            definition.harness.replace("::", "__"),
+            test_name
+        )
+    } else {
+        format!("{}.json", test_name)
+    };
+    let output_file_path_rel = paths.manifest_rel.join("blessed").join(&output_file_name);
+
+    let output_file_path_rel_str = output_file_path_rel
+        .to_str()
+        .ok_or_else(|| {
+            syn::Error::new(
+                test_fn_name.span(),
+                format!(
+                    "Relative output path is not valid UTF-8: {:?}",
+                    output_file_path_rel
+                ),
+            )
+        })?
+        .to_string();
+
+    let mut case_options = serde_json::json!({
+        "redact": definition.redact,
+        "project": definition.project,
+        "cwd": cwd,
+        "new": definition.new,
+        "ignore_keys": definition.ignore_keys,
+        "fuzz": definition.fuzz,
+        "quarantine": definition.quarantine,
+        "per_os": definition.per_os,
+        "loose_whitespace": definition.loose_whitespace,
+        "ignore_case": definition.ignore_case,
+        "seed": definition.seed,
+        "enum_tag": options.enum_tag,
+        "compare_harnesses": definition.compare_harnesses,
+        "max_snapshot_bytes": options.max_snapshot_bytes,
+        "deny_large_snapshots": options.deny_large_snapshots,
+    });
+    // Only present when given, since `null` is a valid expected output
+    if let Some(expected) = definition.expected {
+        case_options["expected"] = expected;
+    }
+
+    Ok(PreparedTest {
+        test_fn_name,
+        test_name,
+        definition_file_rel_str: definition_file_rel_str.to_string(),
+        harness_name: definition.harness,
+        params,
+        compact_copy: options.compact_copies,
+        output_file_path_rel_str,
+        cfg_attr,
+        float_epsilon: definition.float_epsilon,
+        case_options,
+        unfocused: false,
+    })
+}
+
+// Resolves a case's `cwd` against its definition file, relative to the crate root
+fn resolve_cwd(
+    paths: &ProjectPaths,
+    cwd: &str,
+    definition_file_rel_str: &str,
+    test_name: &str,
+) -> Result<String, syn::Error> {
+    let error = |message: &str| {
+        syn::Error::new(
+            proc_macro2::Span::call_site(),
+            format!(
+                "`cwd` {:?} of case '{}' in {}: {}",
+                cwd, test_name, definition_file_rel_str, message
+            ),
+        )
+    };
+    let relative = Path::new(definition_file_rel_str)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(cwd);
+    if !paths.manifest_dir.join(&relative).is_dir() {
+        return Err(error("not a directory"));
+    }
+    relative
+        .to_str()
+        .map(str::to_string)
+        .ok_or_else(|| error("path is not valid UTF-8"))
+}
+
+// Parses a case's `when` predicate, e.g. `cfg(windows)`, into a `#[cfg(...)]` attribute
+fn parse_when(
+    when: &str,
+    definition_file_rel_str: &str,
+    test_name: &str,
+) -> Result<syn::Meta, syn::Error> {
+    let unsupported = || {
+        syn::Error::new(
+            proc_macro2::Span::call_site(),
+            format!(
+                "Unsupported `when` predicate {:?} for case '{}' in {}: expected `cfg(...)`",
+                when, test_name, definition_file_rel_str
+            ),
+        )
+    };
+    match syn::parse_str::<syn::Meta>(when) {
+        Ok(meta @ syn::Meta::List(_)) if meta.path().is_ident("cfg") => Ok(meta),
+        _ => Err(unsupported()),
+    }
+}
+
+// `blessed_{file}` for `src/tests/{file}.blessed.json`, sanitized like test names
+fn file_module_name(definition_file_rel_str: &str) -> String {
+    let file_name = Path::new(definition_file_rel_str)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(definition_file_rel_str);
+    format!(
+        "blessed_{}",
+        blessed_naming::sanitize_test_name(file_name.trim_end_matches(".blessed.json"))
+    )
 }
 
-// Helper function to generate code for a single test function
+// Marks the cases a `blessed.focus` file leaves out, and has it included like
+// a definition file so editing it regenerates the tests
+fn apply_focus(
+    paths: &ProjectPaths,
+    prepared_tests: &mut [PreparedTest],
+    definition_files: &mut Vec<PathBuf>,
+) -> Result<(), syn::Error> {
+    if definition_files.is_empty() {
+        return Ok(()); // reported as such
+    }
+    let error = |message: String| syn::Error::new(proc_macro2::Span::call_site(), message);
+    let Some((path, names)) = focus::read_focus(&paths.manifest_dir).map_err(error)? else {
+        return Ok(());
+    };
+    let unknown: Vec<&str> = names
+        .iter()
+        .filter(|name| !prepared_tests.iter().any(|prep| prep.test_name == **name))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(error(format!(
+            "{} lists cases that don't exist: {}",
+            focus::FOCUS_FILE,
+            unknown.join(", ")
+        )));
+    }
+    for prep in prepared_tests {
+        prep.unfocused = !names.contains(&prep.test_name);
+    }
+    definition_files.push(path);
+    Ok(())
+}
+
+// Rejects cases whose snapshot paths are equal, or equal ignoring case: on
+// case-insensitive filesystems (macOS, Windows) they would overwrite each other.
+// Only the `.json` paths are known here; the actual extension, `per_os`
+// names (`{case}.{os}.json`) and keyed files (`{harness}.inputs.json`) are
+// decided at runtime, so clashes that only exist between those go unnoticed.
+fn check_output_collisions(prepared_tests: &[PreparedTest]) -> Result<(), syn::Error> {
+    let mut seen: HashMap<String, &PreparedTest> = HashMap::new();
+    let mut collisions = Vec::new();
+    for prep in prepared_tests {
+        let key = prep.output_file_path_rel_str.to_lowercase();
+        if let Some(other) = seen.insert(key, prep) {
+            let mut pair = [
+                format!(
+                    "'{}' ({}) -> {}",
+                    other.test_name, other.definition_file_rel_str, other.output_file_path_rel_str
+                ),
+                format!(
+                    "'{}' ({}) -> {}",
+                    prep.test_name, prep.definition_file_rel_str, prep.output_file_path_rel_str
+                ),
+            ];
+            pair.sort();
+            collisions.push(pair.join(" and "));
+        }
+    }
+    if collisions.is_empty() {
+        return Ok(());
+    }
+    collisions.sort();
+    Err(syn::Error::new(
+        proc_macro2::Span::call_site(),
+        format!(
+            "Blessed cases would write the same snapshot file on case-insensitive filesystems; rename one of each pair:\n{}",
+            collisions.join("\n")
+        ),
+    ))
+}
+
+// Helper function to generate code for a single test function: its
+// `CaseInfo` registration, and a `#[test]` handing that to the runtime engine
 fn generate_test_function_code(
     prep: PreparedTest,
-    git_root_path_str: &str,
-    output_dir_abs_str: &str,
+    manifest_depth: usize,
 ) -> proc_macro2::TokenStream {
     let test_fn_name = prep.test_fn_name;
+    // Not upper-cased: case names differing only in letter case have
+    // distinct test fns, and need distinct consts too
+    let case_const_name = quote::format_ident!("__BLESSED_CASE_{}", test_fn_name);
     let test_name_str = prep.test_name;
+    let definition_file_rel_str = prep.definition_file_rel_str;
     let harness_name = prep.harness_name;
-    let params_value = prep.params;
     let output_file_path_rel_str = prep.output_file_path_rel_str;
+    let cfg_attr = prep.cfg_attr.map(|meta| quote! { #[#meta] });
+    let params_json_str_lit = prep.params.to_string();
+    let case_options_json_str_lit = prep.case_options.to_string();
+    let float_epsilon = match prep.float_epsilon {
+        Some(epsilon) => quote! { Some(#epsilon) },
+        None => quote! { None },
+    };
+    let compact_copy = prep.compact_copy;
+    let ignore_attr = prep.unfocused.then(|| {
+        let reason = format!("not in {}", focus::FOCUS_FILE);
+        quote! { #[ignore = #reason] }
+    });
 
-    let params_json_str_lit = params_value.to_string();
-    let output_file_name = format!("{}.json", test_name_str);
+    quote! {
+        #cfg_attr
+        #[allow(non_upper_case_globals)]
+        const #case_const_name: ::blessed::CaseInfo = ::blessed::CaseInfo {
+            file: #definition_file_rel_str,
+            name: #test_name_str,
+            harness: #harness_name,
+            test_fn: stringify!(#test_fn_name),
+            output_path: #output_file_path_rel_str,
+            params: #params_json_str_lit,
+            options: #case_options_json_str_lit,
+            float_epsilon: #float_epsilon,
+            manifest_dir: env!("CARGO_MANIFEST_DIR"),
+            manifest_depth: #manifest_depth,
+            compact_copy: #compact_copy,
+            module_path: module_path!(),
+            package: env!("CARGO_PKG_NAME"),
+        };
 
-    // Pass owned Strings to quote! macro to avoid lifetime issues if needed
-    let git_root_path_str = git_root_path_str.to_string();
-    let output_dir_abs_str = output_dir_abs_str.to_string();
+        #cfg_attr
+        ::inventory::submit! { #case_const_name }
 
-    quote! {
+        #cfg_attr
         #[test]
+        #ignore_attr
         fn #test_fn_name() {
-            let harness_name = #harness_name;
-            let params_json_str = #params_json_str_lit;
-            let params: ::serde_json::Value = ::serde_json::from_str(params_json_str)
-                 .expect("Internal error: Failed to re-parse params JSON string");
-
-            let output_file_name = #output_file_name;
-            let output_dir_abs_str = #output_dir_abs_str;
-            let output_file_path_rel_str = #output_file_path_rel_str;
-            let git_root_path_str = #git_root_path_str;
-
-            let output_path_abs = ::std::path::Path::new(output_dir_abs_str).join(output_file_name);
-
-            let harness = match ::inventory::iter::<::blessed::HarnessFn>
-                .into_iter()
-                .find(|h| h.name == harness_name)
-            {
-                Some(h) => h,
-                None => panic!("Blessed harness function '{}' not found. Available: {:?}",
-                                 harness_name,
-                                 ::inventory::iter::<::blessed::HarnessFn>.into_iter().map(|h| h.name).collect::<Vec<_>>())
-            };
-
-            let result = (harness.func)(params);
-            let output_json = match result {
-                Ok(value) => ::serde_json::to_string_pretty(&value).expect("Failed to serialize result to JSON"),
-                Err(e) => {
-                    let error_output = ::serde_json::json!({ "blessed_error": e });
-                    ::serde_json::to_string_pretty(&error_output).expect("Failed to serialize error to JSON")
-                }
-            };
-
-            // Write Output File
-            if let Some(parent) = output_path_abs.parent() {
-                ::std::fs::create_dir_all(parent).unwrap_or_else(|e|
-                    panic!("Failed to create output directory '{:?}': {}", parent, e)
-                );
-            }
-            ::std::fs::write(&output_path_abs, &output_json).unwrap_or_else(|e|
-                panic!("Failed to write blessed output file '{:?}': {}", output_path_abs, e)
-            );
-
-            // Check Git Status
-            match run_git_status(git_root_path_str, output_file_path_rel_str) {
-                Ok(status_output) => {
-                    let status_trimmed = status_output.trim_start();
-
-                    if status_trimmed.starts_with("??") {
-                        panic!("Blessed test '{}': Untracked file '{}'. Please review and `git add` the file.",
-                                 #test_name_str, output_file_path_rel_str);
-                    } else if status_trimmed.starts_with("M") || status_trimmed.starts_with("AM") {
-                        panic!("Blessed test '{}': File '{}' is modified and differs from the git index. Please review changes and `git add` or revert.",
-                                 #test_name_str, output_file_path_rel_str);
-                    } else if status_trimmed.starts_with("A") || status_output.trim().is_empty() {
-                        // Test passes.
-                    } else if !status_output.trim().is_empty() {
-                        panic!("Blessed test '{}': Unexpected git status for '{}': {:?}. Please check repository state.",
-                                 #test_name_str, output_file_path_rel_str, status_output);
-                    }
-                }
-                Err(e) => {
-                    panic!("Blessed test '{}': Failed to get git status for '{}': {}",
-                             #test_name_str, output_file_path_rel_str, e);
-                }
-            }
+            ::blessed::run_test(&#case_const_name);
         }
     }
 }
 
 #[proc_macro]
 pub fn tests(input: TokenStream) -> TokenStream {
-    let args = parse_macro_input!(input with Punctuated::<LitStr, Token![,]>::parse_terminated);
-    if !args.is_empty() {
-        return syn::Error::new_spanned(args, "No arguments expected")
-            .to_compile_error()
-            .into();
-    }
+    let args = parse_macro_input!(input with OptionList::parse_terminated);
+    let options = match TestsOptions::parse(args) {
+        Ok(options) => options,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
     let paths = match find_project_paths() {
         Ok(p) => p,
         Err(e) => return e.to_compile_error().into(),
     };
 
-    let (prepared_tests, found_files) = match collect_test_definitions(&paths) {
+    let CollectedDefinitions {
+        mut prepared_tests,
+        mut definition_files,
+        parse_errors,
+    } = match collect_test_definitions(&paths, &options) {
         Ok(result) => result,
         Err(e) => return e.to_compile_error().into(),
     };
+    if !parse_errors.is_empty() && !options.defer_parse_errors {
+        let message = format!(
+            "{} blessed definition file(s) failed to parse:\n{}",
+            parse_errors.len(),
+            parse_errors.join("\n")
+        );
+        return syn::Error::new(proc_macro2::Span::call_site(), message)
+            .to_compile_error()
+            .into();
+    }
+    if let Err(e) = check_output_collisions(&prepared_tests) {
+        return e.to_compile_error().into();
+    }
+    if let Err(e) = apply_focus(&paths, &mut prepared_tests, &mut definition_files) {
+        return e.to_compile_error().into();
+    }
 
-    let final_code = if !found_files {
+    let final_code = if definition_files.is_empty() {
         // Generate a single failing test if no files were found
         let error_message = format!(
             "Blessed error: No test definition files found matching glob pattern '{}'",
@@ -411,41 +1296,409 @@ pub fn tests(input: TokenStream) -> TokenStream {
     } else {
         // Proceed with generating tests if files were found
         let num_tests = prepared_tests.len();
-        let output_dir_abs_str = paths
-            .output_dir_abs
-            .to_str()
-            .expect("Output dir path not valid UTF-8")
-            .to_string();
-
-        // Define the helper function once
-        let run_git_status_fn = quote! {
-                #[doc(hidden)]
-                fn run_git_status(git_root: &str, relative_path: &str) -> Result<String, String> {
-                    let output = ::std::process::Command::new("git")
-                        .args(["status", "--porcelain", "--", relative_path])
-                        .current_dir(git_root)
-                        .output()
-                        .map_err(|e| format!("Failed to execute git status: {}", e))?;
-
-                if !output.status.success() {
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    return Err(format!("`git status` failed (exit code: {}): {}", output.status, stderr));
+
+        let unused_harnesses_test = if options.deny_unused_harnesses {
+            let mut used_harnesses: Vec<&str> = prepared_tests
+                .iter()
+                .flat_map(|prep| {
+                    let compared = prep.case_options["compare_harnesses"].as_array();
+                    std::iter::once(prep.harness_name.as_str())
+                        .chain(compared.into_iter().flatten().filter_map(JsonValue::as_str))
+                })
+                .collect();
+            used_harnesses.sort_unstable();
+            used_harnesses.dedup();
+            quote! {
+                #[test]
+                fn blessed_unused_harnesses() {
+                    let unused = ::blessed::unused_harnesses(&[#(#used_harnesses),*]);
+                    if !unused.is_empty() {
+                        panic!("Blessed: harnesses not referenced by any case: {:?}. Add a case or mark them `#[blessed::harness(allow_unused)]`.",
+                                 unused);
+                    }
                 }
-                Ok(String::from_utf8_lossy(&output.stdout).to_string())
             }
+        } else {
+            quote! {}
         };
 
-        let generated_tests = prepared_tests.into_iter().map(|prep| {
-            generate_test_function_code(prep, &paths.git_root_str, &output_dir_abs_str)
-        });
+        // Fails fast on malformed fixtures, listing all of them; their cases aren't generated
+        let parse_errors_test = if options.defer_parse_errors {
+            quote! {
+                #[test]
+                fn blessed_definition_files_parse() {
+                    let errors: &[&str] = &[#(#parse_errors),*];
+                    if !errors.is_empty() {
+                        panic!("Blessed: {} definition file(s) failed to parse:\n{}",
+                                 errors.len(), errors.join("\n"));
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        // Passes, but its name in the test output keeps quarantines from being forgotten
+        let mut quarantined: Vec<&str> = prepared_tests
+            .iter()
+            .filter(|prep| prep.case_options["quarantine"] == true)
+            .map(|prep| prep.test_name.as_str())
+            .collect();
+        quarantined.sort_unstable();
+        let quarantine_test = if quarantined.is_empty() {
+            quote! {}
+        } else {
+            quote! {
+                #[test]
+                fn blessed_quarantined_cases() {
+                    let quarantined: &[&str] = &[#(#quarantined),*];
+                    eprintln!("Blessed: {} case(s) quarantined: {}",
+                              quarantined.len(), quarantined.join(", "));
+                }
+            }
+        };
+
+        // Explains a broken git setup once; cases needing git fail with a pointer here
+        let git_preflight_test = quote! {
+            #[test]
+            fn blessed_git_preflight() {
+                if let Err(e) = ::blessed::git_preflight(env!("CARGO_MANIFEST_DIR")) {
+                    panic!("Blessed: {}", e);
+                }
+            }
+        };
+
+        // Opt-in, since it runs every harness twice over
+        let isolation_test = if options.check_isolation {
+            quote! {
+                #[test]
+                fn blessed_isolation() {
+                    if let Err(e) = ::blessed::check_isolation() {
+                        panic!("Blessed: {}", e);
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let report_test = if options.report {
+            quote! {
+                #[test]
+                fn blessed_report() {
+                    if let Err(e) = ::blessed::write_report(env!("CARGO_MANIFEST_DIR")) {
+                        panic!("Blessed: {}", e);
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let stray_snapshots_test = if options.deny_stray_snapshots {
+            quote! {
+                #[test]
+                fn blessed_stray_snapshots() {
+                    match ::blessed::stray_snapshots(env!("CARGO_MANIFEST_DIR")) {
+                        Ok(stray) if stray.is_empty() => {}
+                        Ok(stray) => panic!(
+                            "Blessed: untracked files in the snapshot directory that no case writes \
+                             (delete them, or list them in .gitignore if intentional):\n  {}",
+                            stray.join("\n  ")
+                        ),
+                        Err(e) => panic!("Blessed: {}", e),
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        // Checked against git like a snapshot, so the settings get committed
+        let gitattributes_test = if options.gitattributes {
+            quote! {
+                #[test]
+                fn blessed_gitattributes() {
+                    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+                    if let Err(e) = ::blessed::ensure_gitattributes(manifest_dir) {
+                        panic!("Blessed: {}", e);
+                    }
+                    let path = "blessed/.gitattributes";
+                    match ::blessed::git_status(manifest_dir, path) {
+                        Ok(status) => {
+                            if let Some(problem) = ::blessed::status_problem(&status, path) {
+                                panic!("Blessed: {}", problem);
+                            }
+                        }
+                        Err(e) => panic!("Blessed: {}", e),
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        // One aggregated list for CI, on top of each case's own check
+        let committed_test = if options.require_committed {
+            quote! {
+                #[test]
+                fn blessed_all_committed() {
+                    match ::blessed::uncommitted_snapshots(env!("CARGO_MANIFEST_DIR")) {
+                        Ok(files) if files.is_empty() => {}
+                        Ok(files) => panic!(
+                            "Blessed: {} file(s) under blessed/ are untracked or differ from the git index \
+                             (review and `git add` them, or remove them):\n  {}",
+                            files.len(),
+                            files.join("\n  ")
+                        ),
+                        Err(e) => panic!("Blessed: {}", e),
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let manifest_depth = paths.manifest_rel.components().count();
+        let generated_tests: Vec<_> = if options.per_file_modules {
+            // Test paths come from `module_path!()`, so reruns and filters
+            // see the nesting without further changes
+            let mut modules: BTreeMap<String, Vec<proc_macro2::TokenStream>> = BTreeMap::new();
+            for prep in prepared_tests {
+                modules
+                    .entry(file_module_name(&prep.definition_file_rel_str))
+                    .or_default()
+                    .push(generate_test_function_code(prep, manifest_depth));
+            }
+            modules
+                .into_iter()
+                .map(|(module, tests)| {
+                    let module = Ident::new(&module, proc_macro2::Span::call_site());
+                    quote! {
+                        mod #module {
+                            #(#tests)*
+                        }
+                    }
+                })
+                .collect()
+        } else {
+            prepared_tests
+                .into_iter()
+                .map(|prep| generate_test_function_code(prep, manifest_depth))
+                .collect()
+        };
 
         eprintln!("Generated {} blessed tests.", num_tests);
 
+        // Cases are compiled in, so running them never reads these files; including
+        // them makes cargo rebuild the tests whenever one is edited
+        let definition_paths = definition_files
+            .iter()
+            .map(|path| path.to_string_lossy().to_string());
+
         quote! {
-            #run_git_status_fn // Include the helper function definition
+            const _: &[&str] = &[#(include_str!(#definition_paths)),*];
+            #parse_errors_test
+            #git_preflight_test
+            #unused_harnesses_test
+            #quarantine_test
+            #isolation_test
+            #report_test
+            #stray_snapshots_test
+            #gitattributes_test
+            #committed_test
             #(#generated_tests)*
         }
     };
 
     TokenStream::from(final_code)
 }
+
+// `golden!(name, |params: T| ..., r#"{"case": params, ...}"#)`
+struct Golden {
+    name: Ident,
+    closure: syn::ExprClosure,
+    cases: syn::LitStr,
+}
+
+impl syn::parse::Parse for Golden {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let closure = input.parse()?;
+        input.parse::<syn::Token![,]>()?;
+        let cases = input.parse()?;
+        if input.peek(syn::Token![,]) {
+            input.parse::<syn::Token![,]>()?;
+        }
+        Ok(Golden {
+            name,
+            closure,
+            cases,
+        })
+    }
+}
+
+// A type as written, for `HarnessFn::signature`: `Result<Regex, ParseError>`
+// rather than the token spacing of `Result < Regex, ParseError >`
+fn type_name(ty: &syn::Type) -> String {
+    let mut name = quote! { #ty }.to_string();
+    for (spaced, tight) in [
+        (" <", "<"),
+        ("< ", "<"),
+        (" >", ">"),
+        (" ,", ","),
+        (" ::", "::"),
+        (":: ", "::"),
+        ("& ", "&"),
+        ("( ", "("),
+        (" )", ")"),
+        ("[ ", "["),
+        (" ]", "]"),
+        (" ;", ";"),
+    ] {
+        name = name.replace(spaced, tight);
+    }
+    name
+}
+
+/// Golden tests for a closure instead of a named harness, for quick checks:
+/// each case in the JSON object becomes a `blessed_test_{name}__{case}` test
+/// snapshotting the closure's output to `blessed/{name}/{case}.json`.
+#[proc_macro]
+pub fn golden(input: TokenStream) -> TokenStream {
+    let golden = parse_macro_input!(input as Golden);
+    match expand_golden(golden) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_golden(golden: Golden) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let Golden {
+        name,
+        closure,
+        cases,
+    } = golden;
+    if closure.inputs.len() != 1 {
+        return Err(syn::Error::new_spanned(
+            &closure.inputs,
+            "The closure must take exactly one (typed) argument, the params",
+        ));
+    }
+    let cases: serde_json::Map<String, JsonValue> =
+        serde_json::from_str(&cases.value()).map_err(|e| {
+            syn::Error::new(
+                cases.span(),
+                format!("Expected a JSON object of case names to params: {}", e),
+            )
+        })?;
+    if cases.is_empty() {
+        return Err(syn::Error::new(
+            name.span(),
+            "`golden!` needs at least one case",
+        ));
+    }
+
+    let input_type = match closure.inputs.first() {
+        Some(syn::Pat::Type(typed)) => type_name(&typed.ty),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &closure.inputs,
+                "The closure's argument must be typed, since params are deserialized into it",
+            ))
+        }
+    };
+    let output_type = match &closure.output {
+        syn::ReturnType::Type(_, ty) => type_name(ty),
+        syn::ReturnType::Default => "_".to_string(),
+    };
+
+    let paths = find_project_paths()?;
+    // Snapshots go to `blessed/{name}/`, so they can't collide with `tests!` cases
+    let options = TestsOptions {
+        per_harness_dirs: true,
+        ..TestsOptions::default()
+    };
+    let name_str = name.to_string();
+    let source = format!("golden!({})", name_str);
+    let mut prepared_tests = Vec::new();
+    for (case_name, params) in cases {
+        let definition: BlessedDefinition =
+            serde_json::from_value(serde_json::json!({ "harness": name_str, "params": params }))
+                .expect("a harness and params always make a definition");
+        prepared_tests.push(prepare_test(
+            &paths, &options, &name_str, &source, case_name, definition,
+        )?);
+    }
+    check_output_collisions(&prepared_tests)?;
+
+    let manifest_depth = paths.manifest_rel.components().count();
+    let generated_tests = prepared_tests
+        .into_iter()
+        .map(|prep| generate_test_function_code(prep, manifest_depth));
+    let wrapper_func_name = Ident::new(&format!("__blessed_golden_{}", name), name.span());
+
+    Ok(quote! {
+        #[doc(hidden)]
+        fn #wrapper_func_name(input_json: ::serde_json::Value) -> Result<::serde_json::Value, String> {
+            let input = ::serde_json::from_value(input_json)
+                .map_err(|e| format!("Failed to deserialize input: {}", e))?;
+            let output = (#closure)(input);
+            ::blessed::serialize_output(output)
+        }
+
+        ::inventory::submit! {
+            ::blessed::HarnessFn {
+                name: #name_str,
+                module_path: module_path!(),
+                func: #wrapper_func_name,
+                // Its only cases are the ones generated here
+                allow_unused: true,
+                description: #source,
+                format: ::blessed::SnapshotFormat::Json,
+                format_version: None,
+                trailing_newline: true,
+                normalize_newlines: false,
+                canonical_numbers: false,
+                sensitive: false,
+                keyed: false,
+                takes_params: true,
+                input_type: Some(#input_type),
+                output_type: #output_type,
+            }
+        }
+
+        #(#generated_tests)*
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matrix_dimensions_with_any_odd_value_are_indexed() {
+        let matrix = serde_json::json!({ "regex": ["1", "x y"], "mode": ["a", "b"] });
+        let names: Vec<String> = expand_matrix(matrix.as_object().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "mode_a_regex_0",
+                "mode_a_regex_1",
+                "mode_b_regex_0",
+                "mode_b_regex_1"
+            ]
+        );
+        let repeated = serde_json::json!({ "regex": ["a", "a"] });
+        let error = expand_matrix(repeated.as_object().unwrap()).unwrap_err();
+        assert_eq!(
+            error,
+            "`matrix` dimension 'regex' lists \"a\" twice (at index 1)"
+        );
+    }
+}
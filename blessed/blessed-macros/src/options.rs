@@ -0,0 +1,203 @@
+// Parsing for the arguments of `#[harness(...)]` and `tests!(...)`
+
+use syn::{punctuated::Punctuated, Meta, Token};
+
+pub(crate) type OptionList = Punctuated<Meta, Token![,]>;
+
+#[derive(Default)]
+pub(crate) struct HarnessOptions {
+    // Exempts the harness from `tests!(deny_unused_harnesses)`
+    pub allow_unused: bool,
+    // Overrides the description taken from the doc comment
+    pub desc: Option<String>,
+    // Snapshot the output's `{:#?}` as text instead of serializing it
+    pub debug: bool,
+    // Recorded as `_format` in every snapshot; a mismatch forces a re-bless
+    pub format_version: Option<u32>,
+    // Snapshot a `String` output verbatim as text
+    pub text: bool,
+    // For text snapshots: `false` writes the output exactly, without
+    // ensuring it ends in a newline
+    pub trailing_newline: Option<bool>,
+    // Check the serialized output against the return type's JSON Schema
+    pub schema: bool,
+    // Rewrite `\r\n` to `\n` inside string values of the output
+    pub normalize_newlines: bool,
+    // Write integral floats as integers, so `1.0` and `1` snapshot the same
+    pub canonical_numbers: bool,
+    // Pass `params` to the harness as a `serde_json::Value`, without
+    // deserializing
+    pub raw_input: bool,
+    // Keep the output out of failure messages and logs
+    pub sensitive: bool,
+    // Merge every case's output into one file keyed by input
+    pub keyed: bool,
+    // Write a sequence output one element per line to `{name}.ndjson`
+    pub ndjson: bool,
+}
+
+impl HarnessOptions {
+    pub fn parse(args: OptionList) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for meta in args {
+            match option_name(&meta).as_str() {
+                "allow_unused" => options.allow_unused = flag(&meta)?,
+                "desc" => options.desc = Some(string(&meta)?),
+                "debug" => options.debug = flag(&meta)?,
+                "format_version" => options.format_version = Some(integer(&meta)?),
+                "text" => options.text = flag(&meta)?,
+                "trailing_newline" => options.trailing_newline = Some(flag(&meta)?),
+                "schema" => options.schema = flag(&meta)?,
+                "normalize_newlines" => options.normalize_newlines = flag(&meta)?,
+                "canonical_numbers" => options.canonical_numbers = flag(&meta)?,
+                "raw_input" => options.raw_input = flag(&meta)?,
+                "sensitive" => options.sensitive = flag(&meta)?,
+                "keyed" => options.keyed = flag(&meta)?,
+                "ndjson" => options.ndjson = flag(&meta)?,
+                _ => return Err(syn::Error::new_spanned(meta, "Unknown harness option")),
+            }
+        }
+        let error = |message| Err(syn::Error::new(proc_macro2::Span::call_site(), message));
+        if options.text && options.debug {
+            return error("A harness can't be both `text` and `debug`");
+        }
+        if options.trailing_newline.is_some() && !options.text && !options.debug {
+            return error("`trailing_newline` only applies to `text` and `debug` harnesses");
+        }
+        if options.schema && (options.text || options.debug) {
+            return error("`schema` only applies to JSON snapshots, not `text` or `debug`");
+        }
+        if options.ndjson && (options.text || options.debug) {
+            return error("A harness can't be both `ndjson` and `text` or `debug`");
+        }
+        if options.ndjson && options.format_version.is_some() {
+            return error("`format_version` only applies to JSON snapshots, not `ndjson`");
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct TestsOptions {
+    // Generate a test failing for harnesses no case refers to
+    pub deny_unused_harnesses: bool,
+    // Write snapshots to `blessed/{harness}/{case}.json`
+    pub per_harness_dirs: bool,
+    // Write snapshots to `blessed/{harness}__{case}.json`
+    pub harness_prefixed_names: bool,
+    // Also write a compact `{case}.min.json` next to each pretty snapshot
+    pub compact_copies: bool,
+    // Accept definition files without cases instead of failing the build
+    pub allow_empty_files: bool,
+    // Report malformed definition files from a generated test instead of
+    // failing the build
+    pub defer_parse_errors: bool,
+    // Rewrite externally tagged enums in JSON snapshots to carry their
+    // variant under this key
+    pub enum_tag: Option<String>,
+    // Generate a test rerunning cases to catch harnesses leaking global state
+    pub check_isolation: bool,
+    // Generate a test writing `blessed/REPORT.md`, a catalog of the cases
+    pub report: bool,
+    // Generate a test failing on untracked files in `blessed/` no case writes
+    pub deny_stray_snapshots: bool,
+    // Generate a test maintaining diff settings in `blessed/.gitattributes`
+    pub gitattributes: bool,
+    // Generate a test failing on any untracked or modified file in `blessed/`
+    pub require_committed: bool,
+    // Snapshots over this many bytes are reported
+    pub max_snapshot_bytes: Option<u64>,
+    // Oversized snapshots fail their case instead of printing a warning
+    pub deny_large_snapshots: bool,
+    // Nest each definition file's tests in a `mod blessed_{file}`
+    pub per_file_modules: bool,
+}
+
+impl TestsOptions {
+    pub fn parse(args: OptionList) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for meta in args {
+            match option_name(&meta).as_str() {
+                "deny_unused_harnesses" => options.deny_unused_harnesses = flag(&meta)?,
+                "per_harness_dirs" => options.per_harness_dirs = flag(&meta)?,
+                "harness_prefixed_names" => options.harness_prefixed_names = flag(&meta)?,
+                "compact_copies" => options.compact_copies = flag(&meta)?,
+                "allow_empty_files" => options.allow_empty_files = flag(&meta)?,
+                "defer_parse_errors" => options.defer_parse_errors = flag(&meta)?,
+                "enum_tag" => options.enum_tag = Some(string(&meta)?),
+                "check_isolation" => options.check_isolation = flag(&meta)?,
+                "report" => options.report = flag(&meta)?,
+                "deny_stray_snapshots" => options.deny_stray_snapshots = flag(&meta)?,
+                "gitattributes" => options.gitattributes = flag(&meta)?,
+                "require_committed" => options.require_committed = flag(&meta)?,
+                "max_snapshot_bytes" => options.max_snapshot_bytes = Some(integer(&meta)?),
+                "deny_large_snapshots" => options.deny_large_snapshots = flag(&meta)?,
+                "per_file_modules" => options.per_file_modules = flag(&meta)?,
+                _ => return Err(syn::Error::new_spanned(meta, "Unknown tests! option")),
+            }
+        }
+        if options.per_harness_dirs && options.harness_prefixed_names {
+            return Err(syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "`per_harness_dirs` and `harness_prefixed_names` both name the harness; pick one",
+            ));
+        }
+        Ok(options)
+    }
+}
+
+fn option_name(meta: &Meta) -> String {
+    meta.path()
+        .get_ident()
+        .map(|ident| ident.to_string())
+        .unwrap_or_default()
+}
+
+// A bare `name` sets a flag; `name = false` is accepted for symmetry
+fn flag(meta: &Meta) -> syn::Result<bool> {
+    match meta {
+        Meta::Path(_) => Ok(true),
+        Meta::NameValue(nv) => match &nv.value {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Bool(b),
+                ..
+            }) => Ok(b.value),
+            _ => Err(syn::Error::new_spanned(
+                &nv.value,
+                "Expected `true` or `false`",
+            )),
+        },
+        Meta::List(_) => Err(syn::Error::new_spanned(meta, "Expected a flag")),
+    }
+}
+
+fn string(meta: &Meta) -> syn::Result<String> {
+    match meta {
+        Meta::NameValue(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(s),
+                    ..
+                }),
+            ..
+        }) => Ok(s.value()),
+        _ => Err(syn::Error::new_spanned(meta, "Expected `name = \"...\"`")),
+    }
+}
+
+fn integer<T: std::str::FromStr>(meta: &Meta) -> syn::Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    match meta {
+        Meta::NameValue(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Int(i),
+                    ..
+                }),
+            ..
+        }) => i.base10_parse(),
+        _ => Err(syn::Error::new_spanned(meta, "Expected `name = <integer>`")),
+    }
+}
@@ -0,0 +1,48 @@
+// Name mangling for generated tests, shared by blessed-macros and blessed so
+// tools computing test names get exactly what `tests!` generates
+
+/// Maps an arbitrary file or case name onto a valid identifier fragment, the
+/// way generated test names are built. Characters other than letters, digits
+/// and `_` become `_`; since that can make distinct names equal (`a-b` and
+/// `a_b`), such a name also gets a short hash of the original appended, e.g.
+/// `a_b_04644883`.
+pub fn sanitize_test_name(name: &str) -> String {
+    let sanitized = name.replace(|c: char| !c.is_alphanumeric(), "_");
+    if name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        sanitized
+    } else {
+        format!("{}_{:08x}", sanitized, name_hash(name) as u32)
+    }
+}
+
+// FNV-1a, like the runtime's `stable_hash`, which this crate can't depend on
+fn name_hash(name: &str) -> u64 {
+    name.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The name of the `#[test]` generated for `case_name` in the definition file
+/// named `definition_file_name` (e.g. `tests.blessed.json`).
+pub fn test_fn_name(definition_file_name: &str, case_name: &str) -> String {
+    format!(
+        "blessed_test_{}__{}",
+        sanitize_test_name(definition_file_name.trim_end_matches(".blessed.json")),
+        sanitize_test_name(case_name)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitized_names_stay_distinct() {
+        assert_eq!(sanitize_test_name("a_b"), "a_b");
+        let dashed = sanitize_test_name("a-b");
+        let dotted = sanitize_test_name("a.b");
+        assert!(dashed.starts_with("a_b_") && dotted.starts_with("a_b_"));
+        assert_ne!(dashed, dotted);
+        assert_eq!(dashed, sanitize_test_name("a-b"));
+    }
+}
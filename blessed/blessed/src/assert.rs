@@ -0,0 +1,44 @@
+// Golden checks from hand-written tests, without `tests!`
+//
+// `assert_no_changes` builds the same `CaseInfo` a generated test would, then
+// runs it through `run_test`, so snapshots, git checks and failure messages
+// are all shared with generated tests.
+
+use crate::{git, run_test, CaseInfo};
+use serde::Serialize;
+
+/// Runs `harness` on `params`, writes the snapshot to `blessed/{name}.json`
+/// (or `.txt`) under the calling crate, and checks it against git, panicking
+/// with the same message a generated test would. Call it from a `#[test]`
+/// run by `cargo test`, which sets `CARGO_MANIFEST_DIR`.
+#[track_caller]
+pub fn assert_no_changes(harness: &str, params: impl Serialize, name: &str) {
+    let leak = |value: String| -> &'static str { Box::leak(value.into_boxed_str()) };
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+        .expect("CARGO_MANIFEST_DIR is not set; run under `cargo test` to check snapshots");
+    let prefix = git::show_prefix(&manifest_dir)
+        .unwrap_or_else(|e| panic!("Blessed: cannot locate the git root: {}", e));
+    let params = serde_json::to_string(&params)
+        .unwrap_or_else(|e| panic!("Blessed: failed to serialize params: {}", e));
+    // libtest names each test's thread after the test, for the rerun command
+    let test_path = match std::thread::current().name() {
+        Some(thread) if thread != "main" => thread.to_string(),
+        _ => name.to_string(),
+    };
+    let case = CaseInfo {
+        file: "",
+        name: leak(name.to_string()),
+        harness: leak(harness.to_string()),
+        test_fn: leak(test_path),
+        output_path: leak(format!("{}blessed/{}.json", prefix, name)),
+        params: leak(params),
+        options: "{}",
+        float_epsilon: None,
+        manifest_depth: prefix.matches('/').count(),
+        manifest_dir: leak(manifest_dir),
+        compact_copy: false,
+        module_path: "",
+        package: leak(std::env::var("CARGO_PKG_NAME").unwrap_or_default()),
+    };
+    run_test(&case);
+}
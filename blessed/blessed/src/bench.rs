@@ -0,0 +1,29 @@
+// Cases doubling as benchmarks (`bench` feature): each case's harness is
+// timed by criterion on the case's params
+
+use crate::{find_harness, run, CaseInfo};
+use criterion::Criterion;
+
+/// Registers a criterion benchmark for every discovered case, named after
+/// its generated test, timing its harness on the case's params (after
+/// params hooks, in its `cwd`, with its seed). Cases whose harness isn't
+/// compiled into the benchmark target are skipped.
+pub fn bench_cases(c: &mut Criterion) {
+    let mut cases: Vec<&CaseInfo> = inventory::iter::<CaseInfo>.into_iter().collect();
+    cases.sort_by_key(|case| case.test_fn);
+    for case in cases {
+        if find_harness(case.harness).is_err() {
+            continue;
+        }
+        let (harness, options, params) = match run::prepare(case) {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                eprintln!("Blessed: not benchmarking '{}': {}", case.name, e);
+                continue;
+            }
+        };
+        c.bench_function(case.test_fn, |b| {
+            b.iter(|| run::invoke(case, harness, &options, params.clone()))
+        });
+    }
+}
@@ -0,0 +1,163 @@
+// An orderable, canonical form of snapshot values for tools comparing them
+
+use serde_json::Value as JsonValue;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A snapshot value in canonical form: object keys are sorted on
+/// construction, and values have a total order so they can be sorted, used
+/// as `BTreeMap` keys, and compared deterministically.
+///
+/// Values of different types order as null < booleans < numbers < strings <
+/// arrays < objects. Numbers compare numerically, with the JSON spelling
+/// breaking ties (so `1` and `1.0` are distinct but adjacent). Arrays compare
+/// element-wise and objects by their sorted `(key, value)` entries.
+///
+/// `Display` renders compact JSON, which is stable because keys are sorted.
+#[derive(Debug, Clone)]
+pub struct Value(JsonValue);
+
+impl Value {
+    pub fn new(value: JsonValue) -> Self {
+        Value(canonicalize(value))
+    }
+
+    pub fn as_json(&self) -> &JsonValue {
+        &self.0
+    }
+
+    pub fn into_json(self) -> JsonValue {
+        self.0
+    }
+}
+
+impl From<JsonValue> for Value {
+    fn from(value: JsonValue) -> Self {
+        Value::new(value)
+    }
+}
+
+impl From<Value> for JsonValue {
+    fn from(value: Value) -> Self {
+        value.0
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(&self.0, &other.0)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Rebuilds objects in key order, which matters when serde_json preserves
+// insertion order
+fn canonicalize(value: JsonValue) -> JsonValue {
+    match value {
+        JsonValue::Array(items) => JsonValue::Array(items.into_iter().map(canonicalize).collect()),
+        JsonValue::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            JsonValue::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, canonicalize(value)))
+                    .collect(),
+            )
+        }
+        other => other,
+    }
+}
+
+fn rank(value: &JsonValue) -> u8 {
+    match value {
+        JsonValue::Null => 0,
+        JsonValue::Bool(_) => 1,
+        JsonValue::Number(_) => 2,
+        JsonValue::String(_) => 3,
+        JsonValue::Array(_) => 4,
+        JsonValue::Object(_) => 5,
+    }
+}
+
+fn compare(a: &JsonValue, b: &JsonValue) -> Ordering {
+    match (a, b) {
+        (JsonValue::Bool(a), JsonValue::Bool(b)) => a.cmp(b),
+        (JsonValue::Number(a), JsonValue::Number(b)) => compare_numbers(a, b),
+        (JsonValue::String(a), JsonValue::String(b)) => a.cmp(b),
+        (JsonValue::Array(a), JsonValue::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (JsonValue::Object(a), JsonValue::Object(b)) => a
+            .iter()
+            .zip(b)
+            .map(|((a_key, a_value), (b_key, b_value))| {
+                a_key.cmp(b_key).then_with(|| compare(a_value, b_value))
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+// Integers compare exactly; anything involving a float goes through f64
+fn compare_numbers(a: &serde_json::Number, b: &serde_json::Number) -> Ordering {
+    let numeric = match (a.as_i64(), b.as_i64(), a.as_u64(), b.as_u64()) {
+        (Some(a), Some(b), _, _) => a.cmp(&b),
+        (_, _, Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None, _, Some(_)) => Ordering::Less,
+        (None, Some(_), Some(_), _) => Ordering::Greater,
+        _ => {
+            let (a, b) = (a.as_f64().unwrap_or(0.0), b.as_f64().unwrap_or(0.0));
+            a.total_cmp(&b)
+        }
+    };
+    numeric.then_with(|| a.to_string().cmp(&b.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_values_sort() {
+        let mut values: Vec<Value> = [
+            serde_json::json!({"b": 1, "a": [2, "x"]}),
+            serde_json::json!("abc"),
+            serde_json::json!(1.5),
+            serde_json::json!(null),
+            serde_json::json!(-3),
+        ]
+        .into_iter()
+        .map(Value::from)
+        .collect();
+        values.sort();
+        let rendered: Vec<String> = values.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            rendered,
+            ["null", "-3", "1.5", "\"abc\"", r#"{"a":[2,"x"],"b":1}"#]
+        );
+    }
+}
@@ -0,0 +1,69 @@
+// Capturing subprocesses for harnesses that golden-test command-line tools
+
+use crate::case_dir;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// What a subprocess did: its exit code (`None` if it was killed by a
+/// signal) and its output, with machine-specific paths redacted.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CommandOutput {
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs `command` to completion for a harness to return, capturing stdout,
+/// stderr and the exit code.
+///
+/// Without an explicit `current_dir`, the command runs in the case's `cwd`
+/// when it has one. In the captured output, the case's `cwd`, the command's
+/// and the test's current directories, the temp directory and the home
+/// directory are replaced by `[case_dir]`, `[command_dir]`, `[current_dir]`,
+/// `[temp_dir]` and `[home]`, so snapshots don't depend on the machine.
+pub fn run_command(command: &mut Command) -> Result<CommandOutput, String> {
+    if command.get_current_dir().is_none() {
+        if let Some(dir) = case_dir() {
+            command.current_dir(dir);
+        }
+    }
+    let output = command
+        .output()
+        .map_err(|e| format!("Failed to run {:?}: {}", command.get_program(), e))?;
+
+    let current_dir = std::env::current_dir().ok();
+    let mut paths: Vec<(PathBuf, &str)> = [
+        (case_dir(), "[case_dir]"),
+        (
+            command
+                .get_current_dir()
+                .map(|dir| current_dir.clone().unwrap_or_default().join(dir)),
+            "[command_dir]",
+        ),
+        (current_dir, "[current_dir]"),
+        (Some(std::env::temp_dir()), "[temp_dir]"),
+        (std::env::var_os("HOME").map(PathBuf::from), "[home]"),
+    ]
+    .into_iter()
+    .filter_map(|(path, placeholder)| Some((path?, placeholder)))
+    .filter(|(path, _)| path.parent().is_some())
+    .collect();
+    // Longest first, so a directory inside another gets its own placeholder
+    paths.sort_by_key(|(path, _)| std::cmp::Reverse(path.as_os_str().len()));
+
+    let redact = |bytes: &[u8]| {
+        let mut text = String::from_utf8_lossy(bytes).into_owned();
+        for (path, placeholder) in &paths {
+            let path = path.to_string_lossy();
+            let path = path.trim_end_matches(std::path::MAIN_SEPARATOR);
+            text = text.replace(path, placeholder);
+        }
+        text
+    };
+    Ok(CommandOutput {
+        status: output.status.code(),
+        stdout: redact(&output.stdout),
+        stderr: redact(&output.stderr),
+    })
+}
@@ -0,0 +1,52 @@
+// Equivalence checks deciding whether a changed snapshot still passes
+
+use serde_json::Value;
+
+/// Structural equality where numbers within `epsilon` of each other match.
+pub fn approx_eq(a: &Value, b: &Value, epsilon: f64) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => (a - b).abs() <= epsilon,
+            _ => a == b,
+        },
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| approx_eq(a, b, epsilon))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| approx_eq(a, b, epsilon)))
+        }
+        _ => a == b,
+    }
+}
+
+/// Equality of texts, optionally ignoring trailing whitespace on each line
+/// and blank lines (a case's `loose_whitespace`) and letter case (its
+/// `ignore_case`). Both sides are normalized the same way.
+pub(crate) fn texts_match(a: &str, b: &str, loose_whitespace: bool, ignore_case: bool) -> bool {
+    fn significant_lines(text: &str) -> impl Iterator<Item = &str> {
+        text.lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+    }
+    let (a, b) = if ignore_case {
+        (a.to_lowercase(), b.to_lowercase())
+    } else {
+        (a.to_string(), b.to_string())
+    };
+    if loose_whitespace {
+        significant_lines(&a).eq(significant_lines(&b))
+    } else {
+        a == b
+    }
+}
+
+// Drops `keys` from a top-level object; other values are left alone
+pub(crate) fn remove_keys(value: &mut Value, keys: &[String]) {
+    if let Value::Object(map) = value {
+        for key in keys {
+            map.remove(key);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// Expensive immutable data shared by every harness, built once per test
+// binary: a loaded dictionary, a compiled grammar, ...
+//
+// A context is keyed by its type. It is either registered explicitly with
+// `register_context`, or built lazily on first access by the function marked
+// `#[blessed::context]` that returns it.
+
+use std::any::{Any, TypeId};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+type Shared = &'static (dyn Any + Send + Sync);
+
+/// Builds a context on first access. Register one with `#[blessed::context]`.
+pub struct ContextFn {
+    pub type_id: fn() -> TypeId,
+    pub init: fn() -> Box<dyn Any + Send + Sync>,
+}
+
+inventory::collect!(ContextFn);
+
+// One slot per type; the map lock is only held to find the slot, so building
+// one context may read another
+static CONTEXTS: Mutex<BTreeMap<TypeId, &'static OnceLock<Shared>>> = Mutex::new(BTreeMap::new());
+
+fn slot(type_id: TypeId) -> &'static OnceLock<Shared> {
+    let mut contexts = CONTEXTS.lock().unwrap_or_else(|e| e.into_inner());
+    contexts
+        .entry(type_id)
+        .or_insert_with(|| Box::leak(Box::new(OnceLock::new())))
+}
+
+/// Makes `value` the context of type `T` for the rest of the run.
+///
+/// Panics if a context of that type was already registered or built.
+pub fn register_context<T: Any + Send + Sync>(value: T) {
+    let value: Shared = Box::leak(Box::new(value));
+    if slot(TypeId::of::<T>()).set(value).is_err() {
+        panic!(
+            "A blessed context of type `{}` is already registered",
+            std::any::type_name::<T>()
+        );
+    }
+}
+
+/// The shared context of type `T`, building it with its `#[blessed::context]`
+/// function on first access.
+///
+/// Panics if no context of that type is registered.
+pub fn context<T: Any + Send + Sync>() -> &'static T {
+    let value = slot(TypeId::of::<T>()).get_or_init(|| {
+        let init = inventory::iter::<ContextFn>
+            .into_iter()
+            .find(|context| (context.type_id)() == TypeId::of::<T>())
+            .unwrap_or_else(|| {
+                panic!(
+                    "No blessed context of type `{}` is registered; call `blessed::register_context` \
+                     first, or mark a function returning it `#[blessed::context]`",
+                    std::any::type_name::<T>()
+                )
+            })
+            .init;
+        Box::leak(init())
+    });
+    value
+        .downcast_ref()
+        .expect("contexts are stored under their own type")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contexts_can_be_registered_explicitly() {
+        struct Limit(u32);
+        register_context(Limit(3));
+        assert_eq!(context::<Limit>().0, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "No blessed context of type `u8` is registered")]
+    fn missing_contexts_panic() {
+        context::<u8>();
+    }
+}
@@ -0,0 +1,142 @@
+// Comparing two snapshot trees, e.g. `blessed/` against the output of an
+// older version, to validate refactors that shouldn't change outputs
+
+use crate::{unified_diff, Value};
+use std::path::{Path, PathBuf};
+
+/// How the snapshot tree `b` differs from `a`, as returned by [`diff_dirs`].
+/// Paths are relative to the tree roots and sorted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DirDiff {
+    /// Files only in `b`.
+    pub added: Vec<PathBuf>,
+    /// Files only in `a`.
+    pub removed: Vec<PathBuf>,
+    /// Files in both whose contents differ, with a unified diff from `a` to `b`.
+    pub changed: Vec<(PathBuf, String)>,
+}
+
+impl DirDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Walks the trees under `a` and `b` and lists the files added, removed and
+/// changed going from `a` to `b`. JSON files are compared in canonical form,
+/// so key order and formatting don't count as changes; other files are
+/// compared as text.
+pub fn diff_dirs(a: impl AsRef<Path>, b: impl AsRef<Path>) -> Result<DirDiff, String> {
+    let (a, b) = (a.as_ref(), b.as_ref());
+    let a_files = list_files(a)?;
+    let b_files = list_files(b)?;
+
+    let mut diff = DirDiff::default();
+    for path in &a_files {
+        if b_files.binary_search(path).is_err() {
+            diff.removed.push(path.clone());
+        }
+    }
+    for path in &b_files {
+        if a_files.binary_search(path).is_err() {
+            diff.added.push(path.clone());
+            continue;
+        }
+        let old = comparable(&a.join(path))?;
+        let new = comparable(&b.join(path))?;
+        if old != new {
+            diff.changed
+                .push((path.clone(), unified_diff(&old.text(), &new.text(), 3)));
+        }
+    }
+    Ok(diff)
+}
+
+#[derive(PartialEq)]
+enum Contents {
+    Json(Value),
+    Text(String),
+}
+
+impl Contents {
+    fn text(&self) -> String {
+        match self {
+            Contents::Json(value) => {
+                serde_json::to_string_pretty(value.as_json()).unwrap_or_default()
+            }
+            Contents::Text(text) => text.clone(),
+        }
+    }
+}
+
+fn comparable(path: &Path) -> Result<Contents, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        if let Ok(value) = serde_json::from_slice(&bytes) {
+            return Ok(Contents::Json(Value::new(value)));
+        }
+    }
+    Ok(Contents::Text(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+// Every file under `root`, relative to it, sorted
+fn list_files(root: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(dir) = pending.pop() {
+        let entries = std::fs::read_dir(root.join(&dir))
+            .map_err(|e| format!("Failed to read directory {:?}: {}", root.join(&dir), e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+            let path = dir.join(entry.file_name());
+            if entry.path().is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_trees_are_diffed() {
+        let root = std::env::temp_dir().join(format!("blessed-dir-diff-{}", std::process::id()));
+        let (a, b) = (root.join("a"), root.join("b"));
+        for (dir, file, contents) in [
+            (&a, "same.json", r#"{"x": 1, "y": [true]}"#),
+            (&b, "same.json", "{\n  \"y\": [true],\n  \"x\": 1\n}"),
+            (&a, "nested/changed.txt", "one\n"),
+            (&b, "nested/changed.txt", "two\n"),
+            (&a, "removed.json", "{}"),
+            (&b, "added.json", "{}"),
+        ] {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        let diff = diff_dirs(&a, &b).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(diff.added, [std::path::PathBuf::from("added.json")]);
+        assert_eq!(diff.removed, [std::path::PathBuf::from("removed.json")]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(
+            diff.changed[0].0,
+            std::path::Path::new("nested/changed.txt")
+        );
+        assert!(
+            diff.changed[0].1.contains("-one\n+two"),
+            "{}",
+            diff.changed[0].1
+        );
+    }
+}
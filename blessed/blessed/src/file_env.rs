@@ -0,0 +1,70 @@
+// Inputs from fixture directories provisioned outside the repo, e.g. large
+// corpora fetched in CI (`{"$file_env": ["CORPUS_DIR", "sample.txt"]}`)
+//
+// Unlike the other params directives, which `tests!` expands at compile time,
+// `$file_env` is resolved each time a case runs, so the directory only has to
+// exist when the tests do.
+
+use serde_json::Value;
+use std::path::Path;
+
+/// Replaces every `{"$file_env": [var, path]}` in `params` with the contents
+/// of `path` (a string) inside the directory named by the environment
+/// variable `var`. Fails if the variable is unset or empty, or the file can't
+/// be read as UTF-8.
+pub fn expand_file_env(params: &mut Value) -> Result<(), String> {
+    match params {
+        Value::Object(map) if map.len() == 1 && map.contains_key("$file_env") => {
+            *params = Value::String(read(&map["$file_env"])?);
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                expand_file_env(value)?;
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                expand_file_env(item)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn read(args: &Value) -> Result<String, String> {
+    let (Some(var), Some(path)) = (args[0].as_str(), args[1].as_str()) else {
+        return Err("`$file_env` expects `[env_var, path]`".to_string());
+    };
+    let dir = match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() => dir,
+        _ => {
+            return Err(format!(
+                "`$file_env`: environment variable `{}` is not set; it should name the directory containing '{}'",
+                var, path
+            ))
+        }
+    };
+    let path = Path::new(&dir).join(path);
+    std::fs::read_to_string(&path)
+        .map_err(|e| format!("`$file_env`: failed to read {:?}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_env_names_the_missing_variable() {
+        let mut params =
+            serde_json::json!([{ "$file_env": ["BLESSED_NO_SUCH_DIR", "sample.txt"] }]);
+        assert_eq!(
+            expand_file_env(&mut params),
+            Err(
+                "`$file_env`: environment variable `BLESSED_NO_SUCH_DIR` is not set; \
+                 it should name the directory containing 'sample.txt'"
+                    .to_string()
+            )
+        );
+    }
+}
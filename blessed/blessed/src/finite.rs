@@ -0,0 +1,433 @@
+// Serializing harness output with a clear error for NaN and infinity
+//
+// serde_json has no representation for non-finite floats, so before
+// converting, the output is walked once by a serializer that only looks for
+// them and remembers where it is, in the same layout the JSON will have. The
+// same walk notes where enum variants are, for `tests!(enum_tag)`, since the
+// JSON alone can't tell `{"Variant": 1}` from a map with one entry.
+
+use serde::ser::{self, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::fmt;
+
+thread_local! {
+    // Where the output last serialized on this thread has enum values
+    static ENUMS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Converts a harness's output to JSON. A NaN or infinite float anywhere in
+/// it is an error naming the JSON pointer of the field, such as
+/// `/stats/mean`, instead of being silently written as `null`.
+pub fn serialize_output<T: Serialize>(output: T) -> Result<Value, String> {
+    let mut walk = Walk::default();
+    let walked = output.serialize(Finder { walk: &mut walk });
+    if let Err(Stop::NonFinite(value)) = walked {
+        let location = if walk.path.is_empty() {
+            "the output itself".to_string()
+        } else {
+            format!("`{}`", pointer(&walk.path))
+        };
+        return Err(format!(
+            "Output has a non-finite float ({}) at {}; JSON can't represent NaN or infinity. \
+             Fix the computation, or map such values to `None` or a string.",
+            value, location
+        ));
+    }
+    ENUMS.with(|enums| enums.replace(walk.enums));
+    serde_json::to_value(output).map_err(|e| format!("Failed to serialize output: {}", e))
+}
+
+/// The JSON pointers of the enum values in the output last converted by
+/// [`serialize_output`] on this thread, innermost first. Draining them means
+/// they can't be applied to another output.
+pub(crate) fn take_enum_pointers() -> Vec<String> {
+    let mut enums = ENUMS.with(|enums| enums.take());
+    enums.sort_by_key(|pointer| std::cmp::Reverse(pointer.matches('/').count()));
+    enums
+}
+
+fn pointer(path: &[String]) -> String {
+    path.iter()
+        .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+// Why the walk ended early; anything other than a non-finite float is left
+// for serde_json to report
+#[derive(Debug)]
+enum Stop {
+    NonFinite(f64),
+    Other,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::NonFinite(value) => write!(f, "non-finite float {}", value),
+            Stop::Other => write!(f, "serialization failed"),
+        }
+    }
+}
+
+impl std::error::Error for Stop {}
+
+impl ser::Error for Stop {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        Stop::Other
+    }
+}
+
+#[derive(Default)]
+struct Walk {
+    // On finding a non-finite float, left pointing at it
+    path: Vec<String>,
+    // Pointers of the externally tagged enum values passed so far
+    enums: Vec<String>,
+}
+
+struct Finder<'a> {
+    walk: &'a mut Walk,
+}
+
+// Every compound shape: array elements are numbered, object entries named by
+// their key, and an enum variant's contents sit under the variant's name
+struct Compound<'a> {
+    walk: &'a mut Walk,
+    index: usize,
+    in_variant: bool,
+}
+
+impl<'a> Finder<'a> {
+    fn compound(self, variant: Option<&'static str>) -> Compound<'a> {
+        if let Some(variant) = variant {
+            self.walk.enums.push(pointer(&self.walk.path));
+            self.walk.path.push(variant.to_string());
+        }
+        Compound {
+            walk: self.walk,
+            index: 0,
+            in_variant: variant.is_some(),
+        }
+    }
+}
+
+impl<'a> ser::Serializer for Finder<'a> {
+    type Ok = ();
+    type Error = Stop;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_f64(self, v: f64) -> Result<(), Stop> {
+        if v.is_finite() {
+            Ok(())
+        } else {
+            Err(Stop::NonFinite(v))
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Stop> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_bool(self, _: bool) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_i8(self, _: i8) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_i16(self, _: i16) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_i32(self, _: i32) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_i64(self, _: i64) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_i128(self, _: i128) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_u8(self, _: u8) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_u16(self, _: u16) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_u32(self, _: u32) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_u64(self, _: u64) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_u128(self, _: u128) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_char(self, _: char) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_str(self, _: &str) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Stop> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, _: &'static str) -> Result<(), Stop> {
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Stop> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Stop> {
+        self.walk.enums.push(pointer(&self.walk.path));
+        self.walk.path.push(variant.to_string());
+        value.serialize(Finder {
+            walk: &mut *self.walk,
+        })?;
+        self.walk.path.pop();
+        Ok(())
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Compound<'a>, Stop> {
+        Ok(self.compound(None))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Compound<'a>, Stop> {
+        Ok(self.compound(None))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Compound<'a>, Stop> {
+        Ok(self.compound(None))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> Result<Compound<'a>, Stop> {
+        Ok(self.compound(Some(variant)))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Compound<'a>, Stop> {
+        Ok(self.compound(None))
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Compound<'a>, Stop> {
+        Ok(self.compound(None))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> Result<Compound<'a>, Stop> {
+        Ok(self.compound(Some(variant)))
+    }
+}
+
+impl Compound<'_> {
+    fn visit<T: ?Sized + Serialize>(&mut self, segment: String, value: &T) -> Result<(), Stop> {
+        self.walk.path.push(segment);
+        value.serialize(Finder {
+            walk: &mut *self.walk,
+        })?;
+        self.walk.path.pop();
+        Ok(())
+    }
+
+    fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Stop> {
+        self.index += 1;
+        self.visit((self.index - 1).to_string(), value)
+    }
+
+    fn finish(self) -> Result<(), Stop> {
+        if self.in_variant {
+            self.walk.path.pop();
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = Stop;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Stop> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Stop> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = Stop;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Stop> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Stop> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Stop;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Stop> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Stop> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Stop;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Stop> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Stop> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = Stop;
+
+    // Keys are named the way they will read in the JSON
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Stop> {
+        let key = match serde_json::to_value(key) {
+            Ok(Value::String(key)) => key,
+            Ok(key) => key.to_string(),
+            Err(_) => return Err(Stop::Other),
+        };
+        self.walk.path.push(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Stop> {
+        value.serialize(Finder {
+            walk: &mut *self.walk,
+        })?;
+        self.walk.path.pop();
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Stop> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = Stop;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Stop> {
+        self.visit(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<(), Stop> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for Compound<'_> {
+    type Ok = ();
+    type Error = Stop;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Stop> {
+        self.visit(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<(), Stop> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn non_finite_floats_are_located() {
+        let stats = BTreeMap::from([("mean", vec![1.5, f64::NAN])]);
+        let error = serialize_output(&stats).unwrap_err();
+        assert!(error.contains("(NaN) at `/mean/1`"), "{}", error);
+        let error = serialize_output(f64::INFINITY).unwrap_err();
+        assert!(error.contains("(inf) at the output itself"), "{}", error);
+        assert_eq!(
+            serialize_output(&stats["mean"][..1]).unwrap(),
+            serde_json::json!([1.5])
+        );
+    }
+}
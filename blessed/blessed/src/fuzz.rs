@@ -0,0 +1,123 @@
+// Cases running a harness over generated inputs, snapshotting the
+// distribution of output shapes
+
+use crate::HarnessFn;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// A small deterministic pseudo-random generator (SplitMix64) handed to
+/// generators, so a pinned seed always yields the same inputs.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`; `bound` must be non-zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    /// A uniformly chosen element of the non-empty `items`.
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// Produces one input for a `fuzz` case. Register one with
+/// `#[blessed::generator]`.
+pub struct GeneratorFn {
+    pub name: &'static str,
+    pub func: fn(&mut Rng) -> Result<Value, String>,
+}
+
+inventory::collect!(GeneratorFn);
+
+/// A case's `fuzz` settings: run the harness `count` times on inputs from
+/// `generator`, seeded with `seed`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Fuzz {
+    pub generator: String,
+    pub count: u32,
+    pub seed: u64,
+}
+
+fn find_generator(name: &str) -> Result<&'static GeneratorFn, String> {
+    inventory::iter::<GeneratorFn>
+        .into_iter()
+        .find(|g| g.name == name)
+        .ok_or_else(|| {
+            format!(
+                "Blessed generator function '{}' not found. Available: {:?}",
+                name,
+                inventory::iter::<GeneratorFn>
+                    .into_iter()
+                    .map(|g| g.name)
+                    .collect::<Vec<_>>()
+            )
+        })
+}
+
+// Runs the harness on every generated input (each first passed through
+// `prepare`, i.e. the params hooks) and counts outputs by shape
+pub(crate) fn run_fuzz(
+    harness: &HarnessFn,
+    fuzz: &Fuzz,
+    prepare: impl Fn(&mut Value),
+) -> Result<Value, String> {
+    let generator = find_generator(&fuzz.generator)?;
+    let mut rng = Rng::new(fuzz.seed);
+    let mut shapes: BTreeMap<String, u32> = BTreeMap::new();
+    for _ in 0..fuzz.count {
+        let mut params = (generator.func)(&mut rng)?;
+        prepare(&mut params);
+        let output =
+            (harness.func)(params).unwrap_or_else(|e| serde_json::json!({ "blessed_error": e }));
+        *shapes.entry(shape(&output).to_string()).or_default() += 1;
+    }
+    Ok(serde_json::json!({
+        "generator": fuzz.generator,
+        "seed": fuzz.seed,
+        "count": fuzz.count,
+        "shapes": shapes,
+    }))
+}
+
+// The value with every leaf replaced by its type name; arrays list the
+// distinct shapes of their elements
+fn shape(value: &Value) -> Value {
+    match value {
+        Value::Null => "null".into(),
+        Value::Bool(_) => "boolean".into(),
+        Value::Number(_) => "number".into(),
+        Value::String(_) => "string".into(),
+        Value::Array(items) => {
+            let mut shapes: Vec<Value> = Vec::new();
+            for item in items.iter().map(shape) {
+                if !shapes.contains(&item) {
+                    shapes.push(item);
+                }
+            }
+            shapes.sort_by_key(|shape| shape.to_string());
+            Value::Array(shapes)
+        }
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), shape(value)))
+                .collect(),
+        ),
+    }
+}
@@ -0,0 +1,308 @@
+// Git plumbing shared by the generated tests
+
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+/// A snapshot's state relative to the git index, from `git status --porcelain`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitStatus {
+    /// Identical to the index (and `HEAD`).
+    Clean,
+    /// Newly staged and unchanged since staging.
+    Added,
+    Untracked,
+    /// Differs from the index.
+    Modified,
+    /// Has unresolved merge conflicts.
+    Conflicted,
+    /// Any other porcelain status, verbatim.
+    Other(String),
+}
+
+/// Gets the status of `relative_path`, with clear errors for repository states
+/// in which snapshots can't be verified (bare repositories, in-progress
+/// rebases or merges, ...).
+pub fn git_status(git_root: &str, relative_path: &str) -> Result<GitStatus, String> {
+    let status_output = match run_git_status(git_root, relative_path) {
+        Ok(status_output) => status_output,
+        Err(e) => return Err(repository_problem(git_root).unwrap_or(e)),
+    };
+
+    let status_trimmed = status_output.trim_start();
+    let code = status_output.get(..2).unwrap_or("");
+    if status_output.trim().is_empty() {
+        Ok(GitStatus::Clean)
+    } else if matches!(code, "DD" | "AU" | "UD" | "UA" | "DU" | "AA" | "UU") {
+        Ok(GitStatus::Conflicted)
+    } else if status_trimmed.starts_with("??") {
+        Ok(GitStatus::Untracked)
+    } else if status_trimmed.starts_with('M') || status_trimmed.starts_with("AM") {
+        Ok(GitStatus::Modified)
+    } else if status_trimmed.starts_with('A') {
+        Ok(GitStatus::Added)
+    } else {
+        match repository_problem(git_root) {
+            Some(problem) => Err(problem),
+            None => Ok(GitStatus::Other(status_output)),
+        }
+    }
+}
+
+/// The failure message for a snapshot in `status`, or `None` if it passes.
+pub fn status_problem(status: &GitStatus, relative_path: &str) -> Option<String> {
+    match status {
+        GitStatus::Clean | GitStatus::Added => None,
+        GitStatus::Untracked => Some(format!(
+            "Untracked file '{}'. Please review and `git add` the file.",
+            relative_path
+        )),
+        GitStatus::Modified => Some(format!(
+            "File '{}' is modified and differs from the git index. Please review changes and `git add` or revert.",
+            relative_path
+        )),
+        GitStatus::Conflicted => Some(format!(
+            "File '{}' has unresolved merge conflicts. Please resolve them, review, and `git add` the file.",
+            relative_path
+        )),
+        GitStatus::Other(status_output) => Some(format!(
+            "Unexpected git status for '{}': {:?}. Please check repository state.",
+            relative_path, status_output
+        )),
+    }
+}
+
+fn run_git_status(git_root: &str, relative_path: &str) -> Result<String, String> {
+    run_git_with_retries(git_root, &["status", "--porcelain", "--", relative_path])
+}
+
+/// Every file under `blessed/` in the crate at `manifest_dir` that is
+/// untracked, differs from the index or is conflicted, as `git status
+/// --porcelain` lines (e.g. `?? examples/foo/blessed/new.json`) with paths
+/// relative to the git root. Staged files whose working copy matches count as
+/// committed, as they do for each case, and ignored files never count.
+///
+/// Other tests may be rewriting snapshots meanwhile, so a non-empty result is
+/// checked again a few times, and only files that stay dirty are returned.
+pub fn uncommitted_snapshots(manifest_dir: &str) -> Result<Vec<String>, String> {
+    let dirty = || -> Result<Vec<String>, String> {
+        let output = run_git_with_retries(
+            manifest_dir,
+            &[
+                "status",
+                "--porcelain",
+                "--untracked-files=all",
+                "--",
+                "blessed",
+            ],
+        )?;
+        Ok(output
+            .lines()
+            .filter(|line| line.as_bytes().get(1).is_some_and(|&code| code != b' '))
+            .map(str::to_string)
+            .collect())
+    };
+    let mut files = dirty()?;
+    for _ in 0..3 {
+        if files.is_empty() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+        let again = dirty()?;
+        files.retain(|file| again.contains(file));
+    }
+    Ok(files)
+}
+
+/// The directory `manifest_dir` relative to its git root, with a trailing
+/// `/` (empty at the root), from `git rev-parse --show-prefix`.
+pub(crate) fn show_prefix(manifest_dir: &str) -> Result<String, String> {
+    run_git_with_retries(manifest_dir, &["rev-parse", "--show-prefix"])
+        .map(|prefix| prefix.trim_end_matches('\n').to_string())
+}
+
+/// Stages `relative_path`, as `BLESS=new` does for new snapshots.
+pub(crate) fn git_add(git_root: &str, relative_path: &str) -> Result<(), String> {
+    run_git_with_retries(git_root, &["add", "--", relative_path]).map(|_| ())
+}
+
+// Parallel tests can race on `.git/index.lock` (status refreshes the index),
+// so that specific failure is retried a few times with exponential backoff.
+// Tunable with `BLESS_GIT_RETRIES` (default 3) and `BLESS_GIT_RETRY_MS`
+// (initial delay, default 50).
+fn run_git_with_retries(git_root: &str, args: &[&str]) -> Result<String, String> {
+    let retries: u32 = env_or("BLESS_GIT_RETRIES", 3);
+    let mut delay = Duration::from_millis(env_or("BLESS_GIT_RETRY_MS", 50));
+
+    let mut attempt = 0;
+    loop {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(git_root)
+            .env("LC_ALL", "C") // the stderr matched below is localized otherwise
+            .output()
+            .map_err(|e| format!("Failed to execute git {}: {}", args[0], e))?;
+
+        if output.status.success() {
+            return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("index.lock") && attempt < retries {
+            attempt += 1;
+            std::thread::sleep(delay);
+            delay *= 2;
+            continue;
+        }
+        return Err(format!(
+            "`git {}` failed (exit code: {}): {}",
+            args[0], output.status, stderr
+        ));
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+/// Checks that `git` can be run and that `manifest_dir` is inside a git work
+/// tree, with a remediation message if not. Run once by the generated
+/// `blessed_git_preflight` test. A rebase or merge in progress is fine here;
+/// it only matters to snapshots whose status it affects, which report it
+/// themselves.
+pub fn git_preflight(manifest_dir: &str) -> Result<(), String> {
+    let installed = Command::new("git")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !installed {
+        return Err(
+            "`git` could not be run. Snapshots are verified against git; install it and make sure it is on PATH."
+                .to_string(),
+        );
+    }
+    let output = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .current_dir(manifest_dir)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(bare_repository_problem(manifest_dir).unwrap_or_else(|| {
+            format!(
+                "'{}' is not inside a git work tree. Snapshots are verified against the git index; run `git init` at the project root.",
+                manifest_dir
+            )
+        }));
+    }
+    Ok(())
+}
+
+fn rev_parse(git_root: &str, arg: &str) -> Option<String> {
+    Command::new("git")
+        .args(["rev-parse", arg])
+        .current_dir(git_root)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn bare_repository_problem(git_root: &str) -> Option<String> {
+    (rev_parse(git_root, "--is-bare-repository").as_deref() == Some("true")).then(|| {
+        format!(
+            "Repository at '{}' is bare; cannot verify snapshot without a work tree.",
+            git_root
+        )
+    })
+}
+
+/// Describes why snapshots can't be verified in the repository at `git_root`,
+/// or `None` if it is in an ordinary state.
+pub fn repository_problem(git_root: &str) -> Option<String> {
+    if let Some(problem) = bare_repository_problem(git_root) {
+        return Some(problem);
+    }
+    let git_dir = Path::new(git_root).join(rev_parse(git_root, "--git-dir")?);
+    let operation = [
+        ("rebase-merge", "rebase"),
+        ("rebase-apply", "rebase"),
+        ("MERGE_HEAD", "merge"),
+        ("CHERRY_PICK_HEAD", "cherry-pick"),
+        ("REVERT_HEAD", "revert"),
+    ]
+    .into_iter()
+    .find(|(marker, _)| git_dir.join(marker).exists())
+    .map(|(_, operation)| operation)?;
+    Some(format!(
+        "Repository is in an in-progress operation ({}); cannot verify snapshot. Finish or abort the {} and rerun.",
+        operation, operation
+    ))
+}
+
+/// Reads `path` (relative to `git_root`) as it exists at `git_ref`, or in the
+/// index when `git_ref` is empty. Returns `Ok(None)` when the ref exists but
+/// the file doesn't.
+pub(crate) fn show_file_at_ref(
+    git_root: &str,
+    git_ref: &str,
+    relative_path: &str,
+) -> Result<Option<String>, String> {
+    show_bytes_at_ref(git_root, git_ref, relative_path)
+        .map(|bytes| bytes.map(|bytes| String::from_utf8_lossy(&bytes).to_string()))
+}
+
+/// Like [`show_file_at_ref`], but returns the contents exactly as committed.
+pub(crate) fn show_bytes_at_ref(
+    git_root: &str,
+    git_ref: &str,
+    relative_path: &str,
+) -> Result<Option<Vec<u8>>, String> {
+    let output = Command::new("git")
+        .args(["show", &format!("{}:{}", git_ref, relative_path)])
+        .current_dir(git_root)
+        .env("LC_ALL", "C") // the stderr matched below is localized otherwise
+        .output()
+        .map_err(|e| format!("Failed to execute git show: {}", e))?;
+
+    if output.status.success() {
+        return Ok(Some(output.stdout));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("does not exist in")
+        || stderr.contains("exists on disk, but not in")
+        || stderr.contains("is not in the index")
+    {
+        return Ok(None);
+    }
+    Err(format!(
+        "`git show {}:{}` failed (exit code: {}): {}",
+        git_ref, relative_path, output.status, stderr
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_in_progress_pass_the_preflight() {
+        let dir = std::env::temp_dir().join(format!("blessed-preflight-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let init = Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        assert!(init.success());
+        std::fs::write(dir.join(".git/MERGE_HEAD"), "").unwrap();
+
+        let root = dir.to_str().unwrap();
+        let preflight = git_preflight(root);
+        let problem = repository_problem(root);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(preflight, Ok(()));
+        assert!(problem.is_some_and(|problem| problem.contains("(merge)")));
+    }
+}
@@ -0,0 +1,89 @@
+// Diff settings for reviewing snapshots (`tests!(gitattributes)`)
+//
+// Blessed owns one marked block in `blessed/.gitattributes` and leaves the
+// rest of the file alone, so projects can add their own attributes around it.
+
+use std::path::Path;
+
+const BEGIN: &str = "# BEGIN blessed";
+const END: &str = "# END blessed";
+
+/// The attributes blessed manages for snapshot files: all kinds are text
+/// with LF line endings, as harnesses write them on every platform.
+pub const GITATTRIBUTES_BLOCK: &str = "# BEGIN blessed
+# Managed by blessed; changes inside this block are overwritten
+*.json text eol=lf
+*.txt text eol=lf
+*.ndjson text eol=lf
+# END blessed
+";
+
+/// Makes `blessed/.gitattributes` under `manifest_dir` contain the current
+/// [`GITATTRIBUTES_BLOCK`], replacing an older version of the block and
+/// keeping any other lines. Returns whether the file changed. With
+/// `BLESS_READONLY` set, an out-of-date file is an error instead.
+pub fn ensure_gitattributes(manifest_dir: &str) -> Result<bool, String> {
+    let path = Path::new(manifest_dir)
+        .join("blessed")
+        .join(".gitattributes");
+    let existing = match std::fs::read_to_string(&path) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Failed to read {:?}: {}", path, e)),
+    };
+
+    let updated = match (existing.find(BEGIN), existing.find(END)) {
+        (Some(begin), Some(end)) if begin < end => {
+            let after = existing[end + END.len()..]
+                .strip_prefix('\n')
+                .unwrap_or(&existing[end + END.len()..]);
+            format!("{}{}{}", &existing[..begin], GITATTRIBUTES_BLOCK, after)
+        }
+        _ if existing.is_empty() || existing.ends_with('\n') => {
+            format!("{}{}", existing, GITATTRIBUTES_BLOCK)
+        }
+        _ => format!("{}\n{}", existing, GITATTRIBUTES_BLOCK),
+    };
+    if updated == existing {
+        return Ok(false);
+    }
+    if crate::readonly() {
+        return Err(format!(
+            "{:?} lacks the current blessed block; run without BLESS_READONLY to update it",
+            path
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+    std::fs::write(&path, updated).map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gitattributes_block_is_replaced_in_place() {
+        let dir =
+            std::env::temp_dir().join(format!("blessed-gitattributes-{}", std::process::id()));
+        let path = dir.join("blessed").join(".gitattributes");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(
+            &path,
+            "*.bin binary\n# BEGIN blessed\nstale\n# END blessed\n*.md text\n",
+        )
+        .unwrap();
+
+        let manifest_dir = dir.to_str().unwrap();
+        assert!(ensure_gitattributes(manifest_dir).unwrap());
+        assert!(!ensure_gitattributes(manifest_dir).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("*.bin binary\n{}*.md text\n", GITATTRIBUTES_BLOCK)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+// Archiving replaced snapshots (`BLESS_HISTORY=1`)
+//
+// Before a snapshot is overwritten with different contents, the old version
+// is copied to `history/{name}/{hash}.{ext}` next to it. The name is the FNV
+// hash of the contents, so archiving is deterministic and archiving the same
+// version again is a no-op.
+
+use crate::stable_hash;
+use std::path::{Path, PathBuf};
+
+/// Whether `BLESS_HISTORY` is set.
+pub fn history_enabled() -> bool {
+    std::env::var_os("BLESS_HISTORY").is_some_and(|value| value != "0")
+}
+
+/// Where the version `contents` of the snapshot at `output_path` is archived.
+pub fn history_path(output_path: &Path, contents: &[u8]) -> PathBuf {
+    let dir = output_path.with_file_name("history");
+    let name = output_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = output_path
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    dir.join(name)
+        .join(format!("{:016x}.{}", stable_hash(contents), extension))
+}
+
+/// Archives the snapshot currently at `output_path` if `new_contents` would
+/// replace it with something different.
+pub(crate) fn archive_previous(output_path: &Path, new_contents: &str) -> Result<(), String> {
+    let Ok(previous) = std::fs::read(output_path) else {
+        return Ok(());
+    };
+    let path = history_path(output_path, &previous);
+    if previous == new_contents.as_bytes() || path.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create history directory '{:?}': {}", parent, e))?;
+    }
+    std::fs::write(&path, &previous)
+        .map_err(|e| format!("Failed to write history file '{:?}': {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_paths_are_content_addressed() {
+        let snapshot = std::path::Path::new("blessed/happy.json");
+        let path = history_path(snapshot, b"{}");
+        assert_eq!(path, history_path(snapshot, b"{}"));
+        assert_ne!(path, history_path(snapshot, b"[]"));
+        assert!(path.starts_with("blessed/history/happy"));
+        assert_eq!(path.extension().unwrap(), "json");
+    }
+}
@@ -0,0 +1,78 @@
+// Skipping harness runs whose inputs haven't changed (`BLESS_INCREMENTAL=1`)
+//
+// A case is fresh when the test binary, its harness name, its params, and the
+// snapshot on disk all hash the same as when the case last ran. The binary's
+// hash stands in for "the harness and everything it calls", so any rebuild
+// reruns every case; a false skip would need an identical binary. Cases with a
+// `cwd` read files the key doesn't cover, so they never skip.
+
+use crate::stable_hash;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Tracks whether one case's harness needs to run again.
+pub struct Incremental {
+    marker_path: PathBuf,
+    inputs_hash: u64,
+}
+
+impl Incremental {
+    /// Returns `None` unless `BLESS_INCREMENTAL` is set to something other
+    /// than `0`.
+    pub fn new(test_id: &str, harness_name: &str, params: &Value) -> Option<Self> {
+        std::env::var_os("BLESS_INCREMENTAL").filter(|value| value != "0")?;
+        let exe_hash = executable_hash()?;
+        let marker_dir = std::env::current_exe()
+            .ok()?
+            .parent()?
+            .join(".blessed-incremental");
+
+        let mut inputs = exe_hash.to_le_bytes().to_vec();
+        inputs.extend_from_slice(harness_name.as_bytes());
+        inputs.push(0);
+        inputs.extend_from_slice(params.to_string().as_bytes());
+
+        Some(Incremental {
+            marker_path: marker_dir.join(test_id),
+            inputs_hash: stable_hash(&inputs),
+        })
+    }
+
+    /// Whether the snapshot at `output_path` is exactly what the last run with
+    /// these inputs wrote.
+    pub fn is_fresh(&self, output_path: &Path) -> bool {
+        match (
+            std::fs::read_to_string(&self.marker_path),
+            self.current_hash(output_path),
+        ) {
+            (Ok(recorded), Some(current)) => recorded.trim() == current.to_string(),
+            _ => false,
+        }
+    }
+
+    /// Remembers the snapshot just written to `output_path` for these inputs.
+    /// Failing to record only costs a rerun next time, so errors are ignored.
+    pub fn record(&self, output_path: &Path) {
+        if let (Some(parent), Some(current)) =
+            (self.marker_path.parent(), self.current_hash(output_path))
+        {
+            let _ = std::fs::create_dir_all(parent);
+            let _ = std::fs::write(&self.marker_path, current.to_string());
+        }
+    }
+
+    fn current_hash(&self, output_path: &Path) -> Option<u64> {
+        let mut bytes = self.inputs_hash.to_le_bytes().to_vec();
+        bytes.extend(std::fs::read(output_path).ok()?);
+        Some(stable_hash(&bytes))
+    }
+}
+
+fn executable_hash() -> Option<u64> {
+    static HASH: OnceLock<Option<u64>> = OnceLock::new();
+    *HASH.get_or_init(|| {
+        let exe = std::env::current_exe().ok()?;
+        std::fs::read(exe).ok().map(|bytes| stable_hash(&bytes))
+    })
+}
@@ -0,0 +1,74 @@
+// Detecting harnesses whose output depends on what ran before them
+// (`tests!(check_isolation)`)
+//
+// Every case is rendered on a fresh thread, so thread-local state can't
+// carry over; then the first case of each harness is rendered again. A
+// harness that reads a `static` it (or another harness) mutated renders
+// differently the second time. This is a diagnostic, not a sandbox: state
+// that only leaks under other orders goes unnoticed.
+
+use crate::{
+    context_lines, find_harness, render_snapshot, run, take_warnings, unified_diff, CaseInfo,
+    REDACTED,
+};
+use std::collections::BTreeMap;
+use std::thread;
+
+/// Runs every discovered case's harness, each on a fresh thread, then reruns
+/// the first case of each harness and fails if its snapshot changed, which
+/// means the harness depends on global state left behind by other runs.
+/// Nothing is written to disk.
+pub fn check_isolation() -> Result<(), String> {
+    let mut cases: Vec<&'static CaseInfo> = inventory::iter::<CaseInfo>.into_iter().collect();
+    cases.sort_by_key(|case| case.test_fn);
+
+    let mut first_runs: BTreeMap<&str, (&'static CaseInfo, String)> = BTreeMap::new();
+    for case in cases {
+        let Some(contents) = render_on_fresh_thread(case) else {
+            continue;
+        };
+        first_runs.entry(case.harness).or_insert((case, contents));
+    }
+
+    let mut problems = Vec::new();
+    for (harness, (case, before)) in first_runs {
+        let Some(after) = render_on_fresh_thread(case) else {
+            continue;
+        };
+        if after != before {
+            let diff = match find_harness(harness) {
+                Ok(harness) if harness.sensitive => format!("{}\n", REDACTED),
+                _ => unified_diff(&before, &after, context_lines()),
+            };
+            problems.push(format!(
+                "harness '{}' gave case '{}' a different snapshot after the other cases ran:\n{}",
+                harness, case.name, diff
+            ));
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Harnesses depend on global state:\n{}",
+            problems.join("\n")
+        ))
+    }
+}
+
+// The case's snapshot as it would be written, or `None` if it can't run here
+// (e.g. its harness isn't compiled in). A panic is part of the result, since
+// a harness panicking only on a rerun is just as order-dependent.
+fn render_on_fresh_thread(case: &'static CaseInfo) -> Option<String> {
+    let (harness, options, params) = run::prepare(case).ok()?;
+    let rendered = thread::spawn(move || {
+        let result = run::invoke(case, harness, &options, params);
+        render_snapshot(harness, result, take_warnings(), &options)
+    })
+    .join();
+    Some(match rendered {
+        Ok(Ok(contents)) => contents,
+        Ok(Err(e)) => format!("error: {}", e),
+        Err(_) => "panicked".to_string(),
+    })
+}
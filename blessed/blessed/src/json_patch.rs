@@ -0,0 +1,63 @@
+// RFC 6902 JSON Patch generation between two snapshot values
+
+use serde_json::{json, Value};
+
+/// Computes the operations turning `old` into `new`.
+///
+/// Objects are diffed key by key and arrays index by index, so the patch is
+/// correct but not necessarily minimal for insertions in the middle of arrays.
+pub fn json_patch(old: &Value, new: &Value) -> Vec<Value> {
+    let mut ops = Vec::new();
+    diff_values(old, new, &mut String::new(), &mut ops);
+    ops
+}
+
+fn diff_values(old: &Value, new: &Value, path: &mut String, ops: &mut Vec<Value>) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let len = push_segment(path, key);
+                match new_map.get(key) {
+                    Some(new_value) => diff_values(old_value, new_value, path, ops),
+                    None => ops.push(json!({ "op": "remove", "path": path })),
+                }
+                path.truncate(len);
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    let len = push_segment(path, key);
+                    ops.push(json!({ "op": "add", "path": path, "value": new_value }));
+                    path.truncate(len);
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            for (i, (old_item, new_item)) in old_items.iter().zip(new_items).enumerate() {
+                let len = push_segment(path, &i.to_string());
+                diff_values(old_item, new_item, path, ops);
+                path.truncate(len);
+            }
+            // Remove from the end so earlier indices stay valid
+            for i in (new_items.len()..old_items.len()).rev() {
+                let len = push_segment(path, &i.to_string());
+                ops.push(json!({ "op": "remove", "path": path }));
+                path.truncate(len);
+            }
+            for new_item in new_items.iter().skip(old_items.len()) {
+                let len = push_segment(path, "-");
+                ops.push(json!({ "op": "add", "path": path, "value": new_item }));
+                path.truncate(len);
+            }
+        }
+        _ if old != new => ops.push(json!({ "op": "replace", "path": path, "value": new })),
+        _ => {}
+    }
+}
+
+// Appends an escaped JSON pointer segment, returning the length to truncate back to
+fn push_segment(path: &mut String, segment: &str) -> usize {
+    let len = path.len();
+    path.push('/');
+    path.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+    len
+}
@@ -0,0 +1,156 @@
+// One snapshot file per harness, keyed by input (`#[harness(keyed)]`)
+//
+// Every case of a keyed harness merges its output into
+// `{qualified harness}.inputs.json` next to where its own snapshot would go, as
+// `{"inputs": {"<input>": <output>}}`. Adding an input adds one entry, and
+// each case is checked against its own entry in the index version of the
+// file, so a change to one input fails only that input's case.
+
+use crate::run::Failure;
+use crate::{
+    context_lines, git, parse_ndjson, unified_diff, HarnessFn, SnapshotFormat, REDACTED, STAGED_REF,
+};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+// Cases of one harness run on parallel test threads; the file is rewritten whole
+static MERGING: Mutex<()> = Mutex::new(());
+
+/// Where the cases of a keyed `harness` write: `{harness}.inputs.json` in the
+/// directory of `output_path`, a case's own snapshot path. The harness is
+/// named by its qualified name with `::` as `__`
+/// (`my_crate__parser__parse.inputs.json`), so same-named harnesses in
+/// different modules get different files.
+pub fn keyed_path(output_path: &str, harness: &HarnessFn) -> String {
+    let file = format!(
+        "{}.inputs.json",
+        harness.qualified_name().replace("::", "__")
+    );
+    match Path::new(output_path).parent() {
+        Some(dir) => dir.join(file).to_string_lossy().to_string(),
+        None => file,
+    }
+}
+
+/// The key of a case's entry: its params as written in the definition file,
+/// verbatim if they are a string and as compact JSON otherwise.
+pub fn input_key(params: &Value) -> String {
+    match params {
+        Value::String(input) => input.clone(),
+        other => other.to_string(),
+    }
+}
+
+// The entry a rendered snapshot becomes: the JSON itself, or a text snapshot
+// as a string
+pub(crate) fn entry(format: SnapshotFormat, contents: &str) -> Result<Value, String> {
+    match format {
+        SnapshotFormat::Json => serde_json::from_str(contents)
+            .map_err(|e| format!("Internal error: rendered snapshot is not JSON: {}", e)),
+        SnapshotFormat::Text => Ok(Value::String(contents.to_string())),
+        SnapshotFormat::Ndjson => parse_ndjson(contents)
+            .map_err(|e| format!("Internal error: rendered snapshot is not NDJSON: {}", e)),
+    }
+}
+
+fn parse(contents: &str, path: &impl std::fmt::Debug) -> Result<BTreeMap<String, Value>, String> {
+    match serde_json::from_str::<Value>(contents) {
+        Ok(Value::Object(mut file)) => match file.remove("inputs") {
+            Some(Value::Object(inputs)) => Ok(inputs.into_iter().collect()),
+            None => Ok(BTreeMap::new()),
+            Some(_) => Err(format!("{:?}: `inputs` is not an object", path)),
+        },
+        _ => Err(format!(
+            "{:?} is not a keyed snapshot file (`{{\"inputs\": {{...}}}}`)",
+            path
+        )),
+    }
+}
+
+/// Sets the entry for `key` in the keyed file at `path`, keeping the other
+/// entries and writing keys in sorted order.
+pub(crate) fn merge_entry(path: &Path, key: &str, value: Value) -> Result<(), String> {
+    let _guard = MERGING.lock().unwrap_or_else(|e| e.into_inner());
+    let mut inputs = match std::fs::read_to_string(path) {
+        Ok(contents) => parse(&contents, &path)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => return Err(format!("Failed to read {:?}: {}", path, e)),
+    };
+    if inputs.get(key) == Some(&value) {
+        return Ok(());
+    }
+    inputs.insert(key.to_string(), value);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory '{:?}': {}", parent, e))?;
+    }
+    let json = serde_json::to_string_pretty(&serde_json::json!({ "inputs": inputs }))
+        .expect("JSON values always serialize");
+    std::fs::write(path, json + "\n")
+        .map_err(|e| format!("Failed to write blessed output file '{:?}': {}", path, e))
+}
+
+// Stages the keyed file at `relative_path`, never while a case is rewriting it
+pub(crate) fn stage(git_root: &str, relative_path: &str) -> Result<(), String> {
+    let _guard = MERGING.lock().unwrap_or_else(|e| e.into_inner());
+    git::git_add(git_root, relative_path)
+}
+
+/// The entry for `key` in the keyed file `relative_path` as of `git_ref`, or
+/// as staged when `git_ref` is empty or [`STAGED_REF`]. `Ok(None)` when the
+/// file or the entry doesn't exist there.
+pub fn committed_entry(
+    git_root: &str,
+    git_ref: &str,
+    relative_path: &str,
+    key: &str,
+) -> Result<Option<Value>, String> {
+    let git_ref = if git_ref == STAGED_REF { "" } else { git_ref };
+    match git::show_file_at_ref(git_root, git_ref, relative_path)? {
+        Some(contents) => Ok(parse(&contents, &relative_path)?.remove(key)),
+        None => Ok(None),
+    }
+}
+
+// Checks a case's new entry against the committed one, `git_ref` being empty
+// for the index; the diff is of the two entries alone
+pub(crate) fn check_entry(
+    harness: &HarnessFn,
+    git_root: &str,
+    git_ref: &str,
+    relative_path: &str,
+    key: &str,
+    value: &Value,
+) -> Result<(), Failure> {
+    let at = if git_ref.is_empty() || git_ref == STAGED_REF {
+        "the git index".to_string()
+    } else {
+        format!("baseline ref '{}'", git_ref)
+    };
+    let committed = committed_entry(git_root, git_ref, relative_path, key)?.ok_or_else(|| {
+        format!(
+            "Input {:?} has no entry in '{}' in {}. Please review and `git add` the file.",
+            key, relative_path, at
+        )
+    })?;
+    if &committed == value {
+        return Ok(());
+    }
+    let problem = format!(
+        "Entry for input {:?} in '{}' differs from {}. Please review changes and `git add` or revert.",
+        key, relative_path, at
+    );
+    if harness.sensitive {
+        return Err(format!("{}\n(diff {})", problem, REDACTED).into());
+    }
+    let pretty = |value: &Value| {
+        serde_json::to_string_pretty(value).expect("JSON values always serialize") + "\n"
+    };
+    let diff = unified_diff(&pretty(&committed), &pretty(value), context_lines());
+    Err(Failure {
+        message: format!("{}\n{}", problem, diff),
+        diff: Some(diff),
+    })
+}
@@ -1,14 +1,763 @@
-use serde_json::Value;
+use serde_json::Value as JsonValue;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
 
+mod assert;
+#[cfg(feature = "bench")]
+mod bench;
+mod canonical;
+mod command;
+mod compare;
+mod context;
+mod diff;
+mod dir_diff;
+mod file_env;
+mod finite;
+mod fuzz;
+mod git;
+mod gitattributes;
+mod history;
+mod incremental;
+mod isolation;
+mod json_patch;
+mod keyed;
+mod record;
+mod redact;
+mod report;
+mod run;
+#[cfg(feature = "schema")]
+mod schema;
+mod snapshot;
+mod stray;
+mod structure;
+
+pub use assert::assert_no_changes;
+#[cfg(feature = "bench")]
+pub use bench::bench_cases;
+pub use blessed_naming::{sanitize_test_name, test_fn_name};
+pub use canonical::Value;
+pub use command::{run_command, CommandOutput};
+pub use compare::approx_eq;
+pub use context::{context, register_context, ContextFn};
+pub use diff::{context_lines, unified_diff};
+pub use dir_diff::{diff_dirs, DirDiff};
+pub use file_env::expand_file_env;
+pub use finite::serialize_output;
+pub use fuzz::{Fuzz, GeneratorFn, Rng};
+pub use git::{
+    git_preflight, git_status, repository_problem, status_problem, uncommitted_snapshots, GitStatus,
+};
+pub use gitattributes::{ensure_gitattributes, GITATTRIBUTES_BLOCK};
+pub use history::{history_enabled, history_path};
+pub use incremental::Incremental;
+pub use isolation::check_isolation;
+pub use json_patch::json_patch;
+pub use keyed::{committed_entry, input_key, keyed_path};
+pub use record::{record_case, recording_enabled, RECORDED_FILE};
+pub use redact::{redact, Redaction};
+pub use report::{markdown_report, write_report, REPORT_PREVIEW_LINES};
+pub use run::{run_all_and_collect, run_case, run_test, CaseResult, Outcome};
+#[cfg(feature = "schema")]
+pub use schema::{validate_output, variant_coverage, EnumCoverage, VariantCoverage};
+pub use snapshot::{
+    canonicalize_numbers, compact_path, normalize_newlines, os_snapshot_path, parse_ndjson,
+    render_snapshot, retag_enums, size_problem, snapshot_path, write_compact_copy, CaseOptions,
+    SnapshotFormat, DEFAULT_MAX_SNAPSHOT_BYTES, FORMAT_VERSION_KEY,
+};
+pub use stray::stray_snapshots;
+pub use structure::structure_diff;
+
+pub use blessed_macros::context;
+pub use blessed_macros::generator;
+pub use blessed_macros::golden;
 pub use blessed_macros::harness;
+pub use blessed_macros::output_hook;
+pub use blessed_macros::params_hook;
 pub use blessed_macros::tests;
-pub use serde::{Serialize, Deserialize};
+pub use serde::{Deserialize, Serialize};
+
+/// Links crates whose `#[harness]` functions should be available to this
+/// crate's `tests!`, e.g. `blessed::link!(shared_harnesses);`.
+///
+/// Registrations only reach the test binary if the defining crate is linked,
+/// and a dependency nothing refers to is dropped by the linker.
+#[macro_export]
+macro_rules! link {
+    ($($krate:ident),* $(,)?) => {
+        $(extern crate $krate as _;)*
+    };
+}
 
-// Potentially add pub use schemars::JsonSchema; later
+#[cfg(feature = "schema")]
+pub use schemars::JsonSchema;
 
 pub struct HarnessFn {
     pub name: &'static str,
-    pub func: fn(Value) -> Result<Value, String>,
+    /// `module_path!()` where the harness is defined, e.g. `my_crate::parser`.
+    pub module_path: &'static str,
+    pub func: fn(JsonValue) -> Result<JsonValue, String>,
+    /// Set by `#[harness(allow_unused)]` for harnesses intentionally without cases.
+    pub allow_unused: bool,
+    /// From `#[harness(desc = "...")]` or the harness's doc comment; may be empty.
+    pub description: &'static str,
+    pub format: SnapshotFormat,
+    /// From `#[harness(format_version = N)]`, recorded as `_format` in snapshots.
+    pub format_version: Option<u32>,
+    /// Whether text snapshots are made to end in a newline; cleared by
+    /// `#[harness(text, trailing_newline = false)]`.
+    pub trailing_newline: bool,
+    /// Set by `#[harness(normalize_newlines)]`: `\r\n` inside string values
+    /// of the output is snapshotted as `\n`.
+    pub normalize_newlines: bool,
+    /// Set by `#[harness(canonical_numbers)]`: floats with an integral value
+    /// are snapshotted as integers.
+    pub canonical_numbers: bool,
+    /// Set by `#[harness(sensitive)]`: the output is written to the snapshot
+    /// as usual, but failure messages and logs show [`REDACTED`] instead of
+    /// diffs or previews of it.
+    pub sensitive: bool,
+    /// Set by `#[harness(keyed)]`: all of the harness's cases share one
+    /// snapshot file, with an entry per input (see [`keyed_path`]).
+    pub keyed: bool,
+    /// False for harnesses without an argument, whose cases must have null `params`.
+    pub takes_params: bool,
+    /// The argument's type as written, e.g. `Case`; `None` without an argument.
+    pub input_type: Option<&'static str>,
+    /// The return type as written, e.g. `Result<Regex, ParseError>`, or `_`
+    /// for a `golden!` closure that doesn't spell it out.
+    pub output_type: &'static str,
+}
+
+inventory::collect!(HarnessFn);
+
+impl HarnessFn {
+    /// The harness's name qualified by its module path, e.g. `my_crate::parser::parse`.
+    pub fn qualified_name(&self) -> String {
+        format!("{}::{}", self.module_path, self.name)
+    }
+
+    /// A readable signature for docs and tooling, e.g. `Case -> Output`.
+    pub fn signature(&self) -> String {
+        format!(
+            "{} -> {}",
+            self.input_type.unwrap_or("()"),
+            self.output_type
+        )
+    }
+
+    // `parse` matches by name; `parser::parse` also needs the trailing
+    // module path segments to match
+    fn is_named(&self, reference: &str) -> bool {
+        match reference.rsplit_once("::") {
+            None => self.name == reference,
+            Some((module, name)) => {
+                self.name == name
+                    && (self.module_path == module
+                        || self.module_path.ends_with(&format!("::{}", module)))
+            }
+        }
+    }
+}
+
+/// Looks up a registered harness by name, either short (`parse`) or
+/// qualified by any trailing part of its module path (`parser::parse`).
+/// A short name shared by harnesses in several modules is an error asking
+/// for a qualified one.
+pub fn find_harness(name: &str) -> Result<&'static HarnessFn, String> {
+    let matches: Vec<&HarnessFn> = inventory::iter::<HarnessFn>
+        .into_iter()
+        .filter(|h| h.is_named(name))
+        .collect();
+    match matches.as_slice() {
+        [harness] => Ok(harness),
+        [] => Err(format!(
+            "Blessed harness function '{}' not found. Available: {:?}",
+            name,
+            inventory::iter::<HarnessFn>
+                .into_iter()
+                .map(|h| h.name)
+                .collect::<Vec<_>>()
+        )),
+        _ => {
+            let mut candidates: Vec<String> = matches.iter().map(|h| h.qualified_name()).collect();
+            candidates.sort();
+            Err(format!(
+                "Blessed harness name '{}' is ambiguous; qualify it with its module path, one of: {:?}",
+                name, candidates
+            ))
+        }
+    }
+}
+
+/// Lists every registered harness as `(name, description)`, sorted by name.
+pub fn list_harnesses() -> Vec<(&'static str, &'static str)> {
+    let mut harnesses: Vec<_> = inventory::iter::<HarnessFn>
+        .into_iter()
+        .map(|h| (h.name, h.description))
+        .collect();
+    harnesses.sort_unstable();
+    harnesses
+}
+
+/// Registered harnesses that no name in `used` refers to and don't allow
+/// being unused, sorted.
+pub fn unused_harnesses(used: &[&str]) -> Vec<&'static str> {
+    let mut unused: Vec<_> = inventory::iter::<HarnessFn>
+        .into_iter()
+        .filter(|h| !h.allow_unused && !used.iter().any(|name| h.is_named(name)))
+        .map(|h| h.name)
+        .collect();
+    unused.sort_unstable();
+    unused
+}
+
+/// Preprocesses every case's `params` before it is handed to its harness.
+/// Register one with `#[blessed::params_hook]`.
+pub struct ParamsHook {
+    pub func: fn(&mut JsonValue),
+}
+
+inventory::collect!(ParamsHook);
+
+/// Rewrites the final text of every snapshot file before it is written, e.g.
+/// to add a license header. Register one with `#[blessed::output_hook]`.
+pub struct OutputHook {
+    pub func: fn(String) -> String,
+}
+
+inventory::collect!(OutputHook);
+
+/// A case discovered by `blessed::tests!()`, registered whether or not it
+/// runs. Carries everything [`run_case`] needs to run it.
+pub struct CaseInfo {
+    /// Definition file the case came from, relative to the crate root.
+    pub file: &'static str,
+    pub name: &'static str,
+    pub harness: &'static str,
+    /// Name of the generated `#[test]` function, for `cargo test` filters.
+    pub test_fn: &'static str,
+    /// Snapshot written by the case, relative to the git root, assuming a
+    /// JSON harness; `case_index()` resolves the actual extension.
+    pub output_path: &'static str,
+    /// Params as JSON, before params hooks run.
+    pub params: &'static str,
+    /// Runtime settings as `CaseOptions` JSON.
+    pub options: &'static str,
+    pub float_epsilon: Option<f64>,
+    /// The defining crate's `CARGO_MANIFEST_DIR`.
+    pub manifest_dir: &'static str,
+    /// How many levels above `manifest_dir` the git root is.
+    pub manifest_depth: usize,
+    /// Set by `tests!(compact_copies)`.
+    pub compact_copy: bool,
+    /// `module_path!()` where `tests!` was invoked.
+    pub module_path: &'static str,
+    /// The defining crate's package name, for `cargo test -p`.
+    pub package: &'static str,
+}
+
+impl CaseInfo {
+    /// The generated test's full name as libtest reports it, e.g.
+    /// `tests::blessed_test_tests__happy`.
+    pub fn test_path(&self) -> String {
+        match self.module_path.split_once("::") {
+            Some((_crate, module)) => format!("{}::{}", module, self.test_fn),
+            None => self.test_fn.to_string(),
+        }
+    }
+
+    /// Where the case's snapshot is on disk, or `None` if its harness isn't
+    /// registered.
+    pub fn snapshot_file(&self) -> Option<PathBuf> {
+        let harness = find_harness(self.harness).ok()?;
+        let git_root = Path::new(self.manifest_dir)
+            .ancestors()
+            .nth(self.manifest_depth)?;
+        Some(git_root.join(snapshot_path(self.output_path, harness.format)))
+    }
+
+    /// A command rerunning just this case, including the `BLESS_*` settings
+    /// of the current run (other than `BLESS_SHARD`, which could skip it).
+    pub fn reproduce_command(&self) -> String {
+        let mut env: Vec<(String, String)> = std::env::vars()
+            .filter(|(key, _)| key.starts_with("BLESS_") && key != "BLESS_SHARD")
+            .collect();
+        env.sort();
+        let mut command: Vec<String> = env
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", key, shell_quote(&value)))
+            .collect();
+        command.push(format!(
+            "cargo test -p {} -- --exact {}",
+            self.package,
+            self.test_path()
+        ));
+        command.join(" ")
+    }
+}
+
+fn shell_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./,:=".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+inventory::collect!(CaseInfo);
+
+/// The authoritative index of discovered cases as JSON, keyed by test
+/// function name, for tools that shouldn't re-glob or parse definition files.
+pub fn case_index() -> JsonValue {
+    let index: serde_json::Map<String, JsonValue> = inventory::iter::<CaseInfo>
+        .into_iter()
+        .map(|case| {
+            (
+                case.test_fn.to_string(),
+                serde_json::json!({
+                    "file": case.file,
+                    "name": case.name,
+                    "harness": case.harness,
+                    "output_path": find_harness(case.harness)
+                        .map(|h| snapshot_path(case.output_path, h.format))
+                        .unwrap_or_else(|_| case.output_path.to_string()),
+                }),
+            )
+        })
+        .collect();
+    JsonValue::Object(index)
+}
+
+/// Lists every discovered case as `(file, name, harness)`, sorted by file then name.
+pub fn list_cases() -> Vec<(&'static str, &'static str, &'static str)> {
+    let mut cases: Vec<_> = inventory::iter::<CaseInfo>
+        .into_iter()
+        .map(|case| (case.file, case.name, case.harness))
+        .collect();
+    cases.sort();
+    cases
+}
+
+/// A discovered case, for custom runners that filter, run and report on cases
+/// themselves instead of through the generated tests.
+#[derive(Clone)]
+pub struct TestCase {
+    /// Definition file the case came from, relative to the crate root.
+    pub file: &'static str,
+    pub name: &'static str,
+    pub harness: &'static str,
+    /// Params as written in the definition file, before params hooks run.
+    pub params: JsonValue,
+    /// Snapshot written by the case, relative to the git root.
+    pub output_path: String,
+    /// Everything else `tests!` recorded about the case.
+    pub info: &'static CaseInfo,
+}
+
+impl TestCase {
+    /// Runs the case like its generated test would; see [`run_case`].
+    pub fn run(&self) -> CaseResult {
+        run_case(self.info)
+    }
+}
+
+/// Every discovered case, ordered by generated test name.
+pub fn cases() -> impl Iterator<Item = TestCase> {
+    let mut cases: Vec<&'static CaseInfo> = inventory::iter::<CaseInfo>.into_iter().collect();
+    cases.sort_by_key(|case| case.test_fn);
+    cases.into_iter().map(|info| TestCase {
+        file: info.file,
+        name: info.name,
+        harness: info.harness,
+        params: serde_json::from_str(info.params)
+            .expect("Internal error: Failed to re-parse params JSON string"),
+        output_path: match find_harness(info.harness) {
+            Ok(h) if h.keyed => keyed_path(info.output_path, h),
+            Ok(h) => snapshot_path(info.output_path, h.format),
+            Err(_) => info.output_path.to_string(),
+        },
+        info,
+    })
+}
+
+/// Whether a case belongs to the shard selected by `BLESS_SHARD=k/N`.
+///
+/// Cases are assigned by a stable hash of their test name, so every machine
+/// agrees on the split. Without `BLESS_SHARD` every case is in the shard.
+pub fn in_current_shard(test_name: &str) -> bool {
+    let spec = match std::env::var("BLESS_SHARD") {
+        Ok(spec) => spec,
+        Err(_) => return true,
+    };
+    let (index, count) = parse_shard(&spec).unwrap_or_else(|| {
+        panic!(
+            "Invalid BLESS_SHARD value {:?}: expected `k/N` with 0 <= k < N",
+            spec
+        )
+    });
+    stable_hash(test_name.as_bytes()) % count == index
+}
+
+fn parse_shard(spec: &str) -> Option<(u64, u64)> {
+    let (index, count) = spec.trim().split_once('/')?;
+    let index: u64 = index.trim().parse().ok()?;
+    let count: u64 = count.trim().parse().ok()?;
+    (index < count).then_some((index, count))
+}
+
+// FNV-1a: stable across platforms and Rust versions, unlike `DefaultHasher`
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+thread_local! {
+    static WARNINGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static CASE_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    static SEED: std::cell::Cell<Option<u64>> = const { std::cell::Cell::new(None) };
+}
+
+/// Records a non-fatal warning for the case currently running on this thread.
+///
+/// Warnings end up under a `warnings` key in the case's snapshot, so they are
+/// reviewed like any other output.
+pub fn warn(message: impl Into<String>) {
+    WARNINGS.with(|warnings| warnings.borrow_mut().push(message.into()));
+}
+
+/// Drains the warnings recorded on this thread so far.
+pub fn take_warnings() -> Vec<String> {
+    WARNINGS.with(|warnings| warnings.take())
+}
+
+/// Adds `warnings` to a snapshot, leaving it untouched when there are none.
+///
+/// Object outputs gain a `warnings` field; anything else (or an object that
+/// already has one) is wrapped as `{"output": ..., "warnings": [...]}`.
+#[doc(hidden)]
+pub fn attach_warnings(output: JsonValue, warnings: Vec<String>) -> JsonValue {
+    if warnings.is_empty() {
+        return output;
+    }
+    let warnings = JsonValue::from(warnings);
+    match output {
+        JsonValue::Object(mut map) if !map.contains_key("warnings") => {
+            map.insert("warnings".to_string(), warnings);
+            JsonValue::Object(map)
+        }
+        output => serde_json::json!({ "output": output, "warnings": warnings }),
+    }
+}
+
+/// The working directory set by the `cwd` field of the case running on this
+/// thread, if any.
+///
+/// Tests run concurrently, so the process's working directory is never
+/// changed; harnesses that read relative paths should resolve them with
+/// [`case_path`] instead.
+pub fn case_dir() -> Option<PathBuf> {
+    CASE_DIR.with(|dir| dir.borrow().clone())
+}
+
+/// Resolves `path` against the running case's `cwd`. Absolute paths, and any
+/// path outside a case with a `cwd`, are returned unchanged.
+pub fn case_path(path: impl AsRef<Path>) -> PathBuf {
+    match case_dir() {
+        Some(dir) => dir.join(path),
+        None => path.as_ref().to_path_buf(),
+    }
+}
+
+/// Runs `f` with [`case_dir`] set to `dir` on this thread.
+#[doc(hidden)]
+pub fn with_case_dir<T>(dir: Option<PathBuf>, f: impl FnOnce() -> T) -> T {
+    // Restores the previous directory even if `f` panics and the panic is
+    // caught, so it can't leak into whatever runs next on this thread.
+    struct Restore(Option<PathBuf>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CASE_DIR.with(|current| current.replace(self.0.take()));
+        }
+    }
+    let _restore = Restore(CASE_DIR.with(|current| current.replace(dir)));
+    f()
+}
+
+/// The running case's `seed`, for harnesses that use randomness to seed
+/// their RNG (e.g. `blessed::Rng::new(seed)`) so the snapshot is stable.
+/// `None` outside a case with a `seed`, where a harness should not rely on
+/// randomness.
+pub fn current_seed() -> Option<u64> {
+    SEED.with(|seed| seed.get())
+}
+
+/// Runs `f` with [`current_seed`] set to `seed` on this thread.
+#[doc(hidden)]
+pub fn with_seed<T>(seed: Option<u64>, f: impl FnOnce() -> T) -> T {
+    // See `with_case_dir`.
+    struct Restore(Option<u64>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SEED.with(|current| current.set(self.0));
+        }
+    }
+    let _restore = Restore(SEED.with(|current| current.replace(seed)));
+    f()
+}
+
+/// The git ref named by `BLESS_BASELINE_REF`, if set.
+///
+/// When set, generated tests compare their output against the snapshot as
+/// committed at that ref (e.g. `origin/main`) instead of the git index. The
+/// special ref [`STAGED_REF`] (`:`) compares against the staged blob itself,
+/// so a snapshot that is staged but stale fails even where git status alone
+/// would pass it.
+pub fn baseline_ref() -> Option<String> {
+    std::env::var("BLESS_BASELINE_REF")
+        .ok()
+        .filter(|git_ref| !git_ref.trim().is_empty())
+}
+
+/// The `BLESS_BASELINE_REF` value naming the git index (`git show :<path>`)
+/// rather than a commit.
+pub const STAGED_REF: &str = ":";
+
+/// Whether `BLESS_READONLY` is set: cases compare their output with the
+/// committed snapshot in memory, for working trees that can't be written to.
+pub fn readonly() -> bool {
+    std::env::var_os("BLESS_READONLY").is_some_and(|value| value != "0")
 }
 
-inventory::collect!(HarnessFn); 
\ No newline at end of file
+// Reads the baseline of `relative_path` at `git_ref`, which may be `STAGED_REF`
+fn read_baseline(git_root: &str, git_ref: &str, relative_path: &str) -> Result<String, String> {
+    let (show_ref, at) = if git_ref == STAGED_REF {
+        ("", "in the git index".to_string())
+    } else {
+        (git_ref, format!("at baseline ref '{}'", git_ref))
+    };
+    git::show_file_at_ref(git_root, show_ref, relative_path)?
+        .ok_or_else(|| format!("File '{}' does not exist {}.", relative_path, at))
+}
+
+// How failure messages name `git_ref`
+fn baseline_name(git_ref: &str) -> String {
+    if git_ref == STAGED_REF {
+        "its staged version".to_string()
+    } else {
+        format!("baseline ref '{}'", git_ref)
+    }
+}
+
+/// Checks freshly generated snapshot `contents` against the version of
+/// `relative_path` committed at `git_ref`, or staged in the index when
+/// `git_ref` is [`STAGED_REF`], describing any difference.
+pub fn check_against_ref(
+    git_root: &str,
+    git_ref: &str,
+    relative_path: &str,
+    contents: &str,
+) -> Result<(), String> {
+    let baseline = read_baseline(git_root, git_ref, relative_path)?;
+    let diff = unified_diff(&baseline, contents, context_lines());
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "File '{}' differs from {}:\n{}",
+            relative_path,
+            baseline_name(git_ref),
+            diff
+        ))
+    }
+}
+
+/// Like [`check_against_ref`], but for a `#[harness(sensitive)]` harness,
+/// whose output must not appear in the failure message.
+pub fn check_against_ref_redacted(
+    git_root: &str,
+    git_ref: &str,
+    relative_path: &str,
+    contents: &str,
+) -> Result<(), String> {
+    if read_baseline(git_root, git_ref, relative_path)? == contents {
+        Ok(())
+    } else {
+        Err(format!(
+            "File '{}' differs from {}: {}",
+            relative_path,
+            baseline_name(git_ref),
+            REDACTED
+        ))
+    }
+}
+
+/// Shown in place of the output of a `#[harness(sensitive)]` harness.
+pub const REDACTED: &str = "[redacted]";
+
+/// Extra detail for the failure message of a snapshot that differs from the
+/// git index, selected by `BLESS_DIFF` (a comma-separated list). Empty unless
+/// a format is requested.
+///
+/// - `json` renders the change as an RFC 6902 JSON Patch from the index
+///   version to the new output.
+/// - `schema` lists paths whose presence or type changed, separately from
+///   value changes.
+pub fn describe_modification(git_root: &str, relative_path: &str, contents: &str) -> String {
+    let requested = std::env::var("BLESS_DIFF").unwrap_or_default();
+    let requested: Vec<&str> = requested.split(',').map(str::trim).collect();
+    let (json, schema) = (requested.contains(&"json"), requested.contains(&"schema"));
+    if !json && !schema {
+        return String::new();
+    }
+
+    let indexed = match git::show_file_at_ref(git_root, "", relative_path) {
+        Ok(Some(indexed)) => indexed,
+        Ok(None) => return String::new(),
+        Err(e) => return format!("\n(Could not read the index version: {})", e),
+    };
+    let (old, new) = match (
+        serde_json::from_str::<JsonValue>(&indexed),
+        serde_json::from_str::<JsonValue>(contents),
+    ) {
+        (Ok(old), Ok(new)) => (old, new),
+        _ => {
+            return "\n(The index or new version is not valid JSON; no structured diff available.)"
+                .to_string()
+        }
+    };
+
+    let mut detail = String::new();
+    if schema {
+        let (removed, added) = structure_diff(&old, &new);
+        if removed.is_empty() && added.is_empty() {
+            detail.push_str("\nStructure unchanged; only values differ.");
+        } else {
+            detail.push_str("\nStructural changes from the index version:");
+            for path in removed {
+                detail.push_str(&format!("\n- {}", path));
+            }
+            for path in added {
+                detail.push_str(&format!("\n+ {}", path));
+            }
+        }
+    }
+    if json {
+        let patch = JsonValue::from(json_patch(&old, &new));
+        detail.push_str(&format!(
+            "\nJSON patch from the index version:\n{}",
+            serde_json::to_string_pretty(&patch).expect("Failed to serialize JSON patch")
+        ));
+    }
+    detail
+}
+
+/// Decides whether a snapshot that differs from the git index is still
+/// acceptable, given a case's `float_epsilon`, `ignore_keys`,
+/// `loose_whitespace` and `ignore_case`.
+///
+/// Top-level `ignore_keys` are left out of the comparison but kept on disk
+/// with their new values, and with `ignore_case` the new casing is kept
+/// likewise. Otherwise an acceptable snapshot has the index version written
+/// back to `output_path`, so the committed representation is kept.
+pub fn restore_if_equivalent(
+    git_root: &str,
+    relative_path: &str,
+    output_path: &Path,
+    contents: &str,
+    float_epsilon: Option<f64>,
+    options: &CaseOptions,
+) -> bool {
+    if float_epsilon.is_none()
+        && options.ignore_keys.is_empty()
+        && !options.loose_whitespace
+        && !options.ignore_case
+    {
+        return false;
+    }
+    let Ok(Some(indexed)) = git::show_file_at_ref(git_root, "", relative_path) else {
+        return false;
+    };
+    match tolerated(&indexed, contents, float_epsilon, options) {
+        Some(true) => std::fs::write(output_path, indexed).is_ok(),
+        Some(false) => true,
+        None => false,
+    }
+}
+
+// `None` if `contents` differs from `baseline` beyond the case's tolerances,
+// otherwise whether writing `baseline` back keeps the committed representation
+// (rather than a difference the case keeps on purpose)
+fn tolerated(
+    baseline: &str,
+    contents: &str,
+    float_epsilon: Option<f64>,
+    options: &CaseOptions,
+) -> Option<bool> {
+    let ignore_keys = &options.ignore_keys;
+    if (options.loose_whitespace || options.ignore_case)
+        && compare::texts_match(
+            baseline,
+            contents,
+            options.loose_whitespace,
+            options.ignore_case,
+        )
+    {
+        return Some(!options.ignore_case);
+    }
+    let equivalent = (float_epsilon.is_some() || !ignore_keys.is_empty())
+        && match (parse_json_snapshot(baseline), parse_json_snapshot(contents)) {
+            (Ok(mut old), Ok(mut new)) => {
+                compare::remove_keys(&mut old, ignore_keys);
+                compare::remove_keys(&mut new, ignore_keys);
+                match float_epsilon {
+                    Some(epsilon) => approx_eq(&old, &new, epsilon),
+                    None => old == new,
+                }
+            }
+            _ => false,
+        };
+    equivalent.then_some(ignore_keys.is_empty())
+}
+
+// A JSON snapshot, or an NDJSON one as the array it was written from
+fn parse_json_snapshot(contents: &str) -> Result<JsonValue, String> {
+    serde_json::from_str(contents)
+        .map_err(|e| e.to_string())
+        .or_else(|_| parse_ndjson(contents))
+}
+
+/// A failure message if the snapshot committed at `relative_path` records a
+/// different `#[harness(format_version)]` than `harness` produces.
+pub fn format_version_problem(
+    git_root: &str,
+    relative_path: &str,
+    harness: &HarnessFn,
+) -> Option<String> {
+    if harness.format != SnapshotFormat::Json {
+        return None;
+    }
+    let indexed = git::show_file_at_ref(git_root, "", relative_path).ok()??;
+    snapshot::format_version_problem(&indexed, relative_path, harness.format_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_state_is_restored_after_a_panic() {
+        let panicked = std::panic::catch_unwind(|| {
+            with_case_dir(Some(PathBuf::from("cases")), || {
+                with_seed(Some(7), || panic!("harness failed"))
+            })
+        });
+        assert!(panicked.is_err());
+        assert_eq!(case_dir(), None);
+        assert_eq!(current_seed(), None);
+    }
+}
@@ -0,0 +1,73 @@
+// Promoting inputs found at runtime, such as shrunk property-test
+// counterexamples, to blessed cases (`BLESS_RECORD=1`)
+//
+// Recorded cases go to their own definition file, `recorded.blessed.json` in
+// the definitions root, so hand-written files are never reformatted. Cases
+// are named after a hash of their harness and params, which makes recording
+// the same input twice a no-op.
+
+use crate::{find_harness, stable_hash};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Name of the definition file `record_case` appends to.
+pub const RECORDED_FILE: &str = "recorded.blessed.json";
+
+// Test threads may record concurrently; the file is rewritten as a whole
+static RECORDING: Mutex<()> = Mutex::new(());
+
+/// Whether `BLESS_RECORD` is set.
+pub fn recording_enabled() -> bool {
+    std::env::var_os("BLESS_RECORD").is_some_and(|value| value != "0")
+}
+
+/// With `BLESS_RECORD` set, adds a case running `harness` on `params` to
+/// `recorded.blessed.json` in the crate's definitions root (`src/`, or
+/// `BLESSED_ROOT`), returning the case's name. Its snapshot is written on the
+/// next run and reviewed like any new one. Without `BLESS_RECORD` this does
+/// nothing and returns `Ok(None)`, so calls can stay in property tests.
+pub fn record_case(harness: &str, params: impl Serialize) -> Result<Option<String>, String> {
+    if !recording_enabled() {
+        return Ok(None);
+    }
+    find_harness(harness)?;
+    let params =
+        serde_json::to_value(params).map_err(|e| format!("Failed to serialize params: {}", e))?;
+    let key = serde_json::to_vec(&(harness, &params)).expect("JSON values always serialize");
+    let name = format!("{}_{:016x}", harness, stable_hash(&key));
+
+    let path = recorded_file()?;
+    let _guard = RECORDING.lock().unwrap_or_else(|e| e.into_inner());
+    let mut cases = match std::fs::read_to_string(&path) {
+        Ok(json) => match serde_json::from_str(&json) {
+            Ok(Value::Object(cases)) => cases,
+            _ => return Err(format!("{:?} is not a JSON object of cases", path)),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => serde_json::Map::new(),
+        Err(e) => return Err(format!("Failed to read {:?}: {}", path, e)),
+    };
+    if cases.contains_key(&name) {
+        return Ok(Some(name));
+    }
+    cases.insert(
+        name.clone(),
+        serde_json::json!({ "harness": harness, "params": params }),
+    );
+    let json = serde_json::to_string_pretty(&cases).expect("JSON values always serialize");
+    std::fs::write(&path, json + "\n").map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+    eprintln!("Blessed: recorded case '{}' in {:?}", name, path);
+    Ok(Some(name))
+}
+
+// `CARGO_MANIFEST_DIR` is set by cargo when running tests, too
+fn recorded_file() -> Result<PathBuf, String> {
+    let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR")
+        .ok_or("CARGO_MANIFEST_DIR is not set; run under `cargo test` to record cases")?;
+    let root = match std::env::var_os("BLESSED_ROOT") {
+        Some(root) if !root.is_empty() => root,
+        _ => "src".into(),
+    };
+    Ok(PathBuf::from(manifest_dir).join(root).join(RECORDED_FILE))
+}
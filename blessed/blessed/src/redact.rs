@@ -0,0 +1,108 @@
+// Redaction of volatile values from harness output before it is written
+
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// One entry of a case's `redact` list.
+///
+/// `path` is a JSON pointer where `*` matches any key or index; without it the
+/// rule considers every leaf. With `matches`, only string values matching the
+/// regex are redacted, so meaningful fixed values can stay visible.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Redaction {
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub matches: Option<String>,
+    #[serde(default = "default_replacement")]
+    pub with: String,
+}
+
+fn default_replacement() -> String {
+    "[redacted]".to_string()
+}
+
+/// Applies `rules` to `value` in order.
+pub fn redact(value: &mut Value, rules: &[Redaction]) -> Result<(), String> {
+    for rule in rules {
+        let pattern = rule
+            .matches
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| format!("Invalid redaction regex: {}", e))?;
+        let applies = |value: &Value| match (&pattern, value) {
+            (None, _) => true,
+            (Some(pattern), Value::String(s)) => pattern.is_match(s),
+            (Some(_), _) => false,
+        };
+        let replacement = Value::String(rule.with.clone());
+
+        match &rule.path {
+            Some(path) => {
+                let segments: Vec<String> = path
+                    .split('/')
+                    .skip(1)
+                    .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+                    .collect();
+                redact_at(value, &segments, &applies, &replacement);
+            }
+            None if pattern.is_none() => {
+                return Err("A redaction without `path` needs `matches`".to_string());
+            }
+            None => redact_leaves(value, &applies, &replacement),
+        }
+    }
+    Ok(())
+}
+
+fn redact_at(
+    value: &mut Value,
+    segments: &[String],
+    applies: &dyn Fn(&Value) -> bool,
+    replacement: &Value,
+) {
+    let Some((segment, rest)) = segments.split_first() else {
+        if applies(value) {
+            *value = replacement.clone();
+        }
+        return;
+    };
+    match value {
+        Value::Object(map) if segment == "*" => map
+            .values_mut()
+            .for_each(|child| redact_at(child, rest, applies, replacement)),
+        Value::Object(map) => {
+            if let Some(child) = map.get_mut(segment) {
+                redact_at(child, rest, applies, replacement);
+            }
+        }
+        Value::Array(items) if segment == "*" => items
+            .iter_mut()
+            .for_each(|child| redact_at(child, rest, applies, replacement)),
+        Value::Array(items) => {
+            if let Some(child) = segment.parse().ok().and_then(|i: usize| items.get_mut(i)) {
+                redact_at(child, rest, applies, replacement);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn redact_leaves(value: &mut Value, applies: &dyn Fn(&Value) -> bool, replacement: &Value) {
+    match value {
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|child| redact_leaves(child, applies, replacement)),
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|child| redact_leaves(child, applies, replacement)),
+        leaf => {
+            if applies(leaf) {
+                *leaf = replacement.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// A Markdown catalog of cases and their snapshots (`tests!(report)`)
+
+use crate::{
+    committed_entry, find_harness, git, input_key, CaseOptions, SnapshotFormat, TestCase, REDACTED,
+};
+use std::path::Path;
+
+/// Snapshot lines shown per case before the preview is cut off.
+pub const REPORT_PREVIEW_LINES: usize = 40;
+
+/// Renders `cases` as Markdown, grouped by definition file: each case's
+/// harness and description, then a preview of its snapshot as committed to
+/// git (the reviewed version, not whatever the current run wrote), of its
+/// entry in a keyed harness's file, or of its inline `expected` output.
+pub fn markdown_report(cases: impl IntoIterator<Item = TestCase>) -> String {
+    let mut cases: Vec<TestCase> = cases.into_iter().collect();
+    cases.sort_by_key(|case| (case.file, case.name));
+
+    let mut report = String::from("# Blessed cases\n");
+    let mut file = None;
+    for case in cases {
+        if file != Some(case.file) {
+            file = Some(case.file);
+            report.push_str(&format!("\n## `{}`\n", case.file));
+        }
+        report.push_str(&format!("\n### `{}`\n\n", case.name));
+        let harness = find_harness(case.harness).ok();
+        match harness.map(|harness| harness.description) {
+            Some("") | None => report.push_str(&format!("Harness `{}`.\n\n", case.harness)),
+            Some(description) => {
+                report.push_str(&format!("Harness `{}`: {}\n\n", case.harness, description))
+            }
+        }
+        let language = match harness.map(|harness| harness.format) {
+            Some(SnapshotFormat::Text) => "text",
+            _ => "json",
+        };
+        if harness.is_some_and(|harness| harness.sensitive) {
+            report.push_str(&format!(
+                "Snapshot not shown: the harness is sensitive ({}).\n",
+                REDACTED
+            ));
+            continue;
+        }
+        let compared = CaseOptions::from_json(case.info.options)
+            .map(|options| options.compare_harnesses)
+            .unwrap_or_default();
+        if !compared.is_empty() {
+            report.push_str(&format!(
+                "No snapshot: checks that harnesses `{}` agree.\n",
+                compared.join("`, `")
+            ));
+            continue;
+        }
+        match snapshot(&case, harness.is_some_and(|harness| harness.keyed)) {
+            Some(contents) => {
+                report.push_str(&format!("```{}\n{}```\n", language, preview(&contents)))
+            }
+            None => report.push_str("No committed snapshot yet.\n"),
+        }
+    }
+    report
+}
+
+/// Writes the report of every discovered case to `blessed/REPORT.md` under
+/// `manifest_dir`, as the test generated by `tests!(report)` does. Does
+/// nothing with `BLESS_READONLY` set.
+pub fn write_report(manifest_dir: &str) -> Result<(), String> {
+    if crate::readonly() {
+        return Ok(());
+    }
+    let path = Path::new(manifest_dir).join("blessed").join("REPORT.md");
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+    std::fs::write(&path, markdown_report(crate::cases()))
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+fn snapshot(case: &TestCase, keyed: bool) -> Option<String> {
+    let options = CaseOptions::from_json(case.info.options).ok()?;
+    if let Some(expected) = options.expected {
+        return match expected {
+            serde_json::Value::String(text) => Some(text),
+            expected => serde_json::to_string_pretty(&expected).ok(),
+        };
+    }
+    let git_root = Path::new(case.info.manifest_dir)
+        .ancestors()
+        .nth(case.info.manifest_depth)?
+        .to_str()?;
+    if keyed {
+        let entry = committed_entry(git_root, "", &case.output_path, &input_key(&case.params));
+        return match entry.ok()?? {
+            serde_json::Value::String(text) => Some(text),
+            entry => serde_json::to_string_pretty(&entry).ok(),
+        };
+    }
+    git::show_file_at_ref(git_root, "", &case.output_path)
+        .ok()
+        .flatten()
+}
+
+// The first lines, each ending in a newline, with a note of how many were cut
+fn preview(contents: &str) -> String {
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut preview: String = lines
+        .iter()
+        .take(REPORT_PREVIEW_LINES)
+        .map(|line| format!("{}\n", line))
+        .collect();
+    if lines.len() > REPORT_PREVIEW_LINES {
+        preview.push_str(&format!(
+            "... ({} more lines)\n",
+            lines.len() - REPORT_PREVIEW_LINES
+        ));
+    }
+    preview
+}
@@ -0,0 +1,731 @@
+// The engine behind generated tests: running a case, writing its snapshot and
+// verifying it against git
+
+use crate::{
+    approx_eq, baseline_name, baseline_ref, check_against_ref, check_against_ref_redacted,
+    compact_path, compare, context_lines, describe_modification, expand_file_env, find_harness,
+    format_version_problem, fuzz, git, git_preflight, git_status, history, history_enabled,
+    in_current_shard, input_key, keyed, keyed_path, os_snapshot_path, parse_ndjson, read_baseline,
+    readonly, render_snapshot, restore_if_equivalent, size_problem, snapshot_path, status_problem,
+    take_warnings, tolerated, unified_diff, with_case_dir, with_seed, write_compact_copy, CaseInfo,
+    CaseOptions, GitStatus, HarnessFn, Incremental, OutputHook, ParamsHook, SnapshotFormat,
+    DEFAULT_MAX_SNAPSHOT_BYTES, REDACTED, STAGED_REF,
+};
+use serde_json::Value;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// How running a case ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// Not in the shard selected by `BLESS_SHARD`.
+    Skipped,
+    /// The message the case's generated test fails with.
+    Failed(String),
+    /// The case failed with this message, but is quarantined, so its
+    /// generated test still passes.
+    Quarantined(String),
+}
+
+/// The result of running one case, as returned by [`run_case`].
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    pub name: &'static str,
+    pub harness: &'static str,
+    /// Name of the generated `#[test]` function.
+    pub test_fn: &'static str,
+    pub outcome: Outcome,
+    /// Unified diff from the git index version of the snapshot, when the case
+    /// failed because its snapshot changed.
+    pub diff: Option<String>,
+}
+
+pub(crate) struct Failure {
+    pub(crate) message: String,
+    pub(crate) diff: Option<String>,
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure {
+            message,
+            diff: None,
+        }
+    }
+}
+
+/// Runs a case exactly like its generated test does: the harness runs, the
+/// snapshot is written, and the result is verified against git. Failures are
+/// reported in the result rather than panicking; a panicking harness still
+/// panics.
+pub fn run_case(case: &CaseInfo) -> CaseResult {
+    let (outcome, diff) = if !in_current_shard(case.test_fn) {
+        eprintln!(
+            "Blessed test '{}': not in BLESS_SHARD, skipping.",
+            case.name
+        );
+        (Outcome::Skipped, None)
+    } else {
+        let result = if needs_git(case) && !git_usable(case) {
+            Err(Failure::from(
+                "git is unusable, see blessed_git_preflight".to_string(),
+            ))
+        } else {
+            run(case)
+        };
+        match result {
+            Ok(()) => (Outcome::Passed, None),
+            Err(failure) if quarantined(case) => {
+                (Outcome::Quarantined(failure.message), failure.diff)
+            }
+            Err(failure) => (Outcome::Failed(failure.message), failure.diff),
+        }
+    };
+    CaseResult {
+        name: case.name,
+        harness: case.harness,
+        test_fn: case.test_fn,
+        outcome,
+        diff,
+    }
+}
+
+/// Runs every discovered case, sorted by test function name, collecting the
+/// results. A panicking harness fails its case instead of the whole run.
+pub fn run_all_and_collect() -> Vec<CaseResult> {
+    let mut cases: Vec<&CaseInfo> = inventory::iter::<CaseInfo>.into_iter().collect();
+    cases.sort_by_key(|case| case.test_fn);
+    cases
+        .into_iter()
+        .map(|case| {
+            panic::catch_unwind(AssertUnwindSafe(|| run_case(case))).unwrap_or_else(|payload| {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic payload".to_string());
+                CaseResult {
+                    name: case.name,
+                    harness: case.harness,
+                    test_fn: case.test_fn,
+                    outcome: Outcome::Failed(format!("Harness panicked: {}", message)),
+                    diff: None,
+                }
+            })
+        })
+        .collect()
+}
+
+/// The body of every generated test: runs `case` and panics if it failed.
+#[doc(hidden)]
+#[track_caller]
+pub fn run_test(case: &CaseInfo) {
+    let outcome = run_case(case).outcome;
+    if let Outcome::Quarantined(message) = &outcome {
+        eprintln!(
+            "Blessed test '{}' is quarantined; not failing on: {}",
+            case.name, message
+        );
+    }
+    if let Outcome::Failed(message) = outcome {
+        panic!(
+            "Blessed test '{}': {}\nTo rerun just this case: {}",
+            case.name,
+            message,
+            case.reproduce_command()
+        );
+    }
+}
+
+// Cases with inline `expected` output never consult git
+fn needs_git(case: &CaseInfo) -> bool {
+    CaseOptions::from_json(case.options).map_or(true, |options| options.expected.is_none())
+}
+
+// Checked once per test binary, so a broken git setup is explained in detail
+// by the preflight test alone, while every case fails with a short pointer to it
+fn git_usable(case: &CaseInfo) -> bool {
+    static USABLE: OnceLock<bool> = OnceLock::new();
+    *USABLE.get_or_init(|| git_preflight(case.manifest_dir).is_ok())
+}
+
+fn quarantined(case: &CaseInfo) -> bool {
+    CaseOptions::from_json(case.options).is_ok_and(|options| options.quarantine)
+}
+
+fn apply_params_hooks(params: &mut Value) {
+    for hook in inventory::iter::<ParamsHook> {
+        (hook.func)(params);
+    }
+}
+
+// Output hooks see the text of a snapshot file as written and checked, so
+// inline `expected` values and keyed entries don't go through them
+fn apply_output_hooks(contents: String) -> String {
+    inventory::iter::<OutputHook>
+        .into_iter()
+        .fold(contents, |contents, hook| (hook.func)(contents))
+}
+
+// A case's harness, options and params (after params hooks), ready to invoke
+pub(crate) fn prepare(case: &CaseInfo) -> Result<(&'static HarnessFn, CaseOptions, Value), String> {
+    let mut params: Value = serde_json::from_str(case.params)
+        .expect("Internal error: Failed to re-parse params JSON string");
+    let harness = find_harness(case.harness)?;
+    if !harness.takes_params && !params.is_null() {
+        return Err(format!(
+            "harness '{}' takes no parameters, but params were provided",
+            harness.name
+        ));
+    }
+    expand_file_env(&mut params).map_err(|e| format!("Case '{}': {}", case.name, e))?;
+    apply_params_hooks(&mut params);
+    let options = CaseOptions::from_json(case.options)?;
+    Ok((harness, options, params))
+}
+
+// Calls the harness the way the case does: in its `cwd`, with its seed, and
+// over generated inputs for a `fuzz` case
+pub(crate) fn invoke(
+    case: &CaseInfo,
+    harness: &HarnessFn,
+    options: &CaseOptions,
+    params: Value,
+) -> Result<Value, String> {
+    let case_dir = options
+        .cwd
+        .as_deref()
+        .map(|cwd| Path::new(case.manifest_dir).join(cwd));
+    with_case_dir(case_dir, || {
+        with_seed(options.seed, || match &options.fuzz {
+            Some(fuzz) => fuzz::run_fuzz(harness, fuzz, apply_params_hooks),
+            None => (harness.func)(params),
+        })
+    })
+}
+
+fn run(case: &CaseInfo) -> Result<(), Failure> {
+    let (harness, options, params) = prepare(case)?;
+    // Kept for the detailed failure report, since the harness consumes them
+    let verbose_params = (verbose_failures() && !harness.sensitive).then(|| params.clone());
+
+    let manifest_dir = Path::new(case.manifest_dir);
+    let run_harness = |params: Value| {
+        take_warnings(); // don't inherit warnings from a previous case on this thread
+        let result = invoke(case, harness, &options, params);
+        render_snapshot(harness, result, take_warnings(), &options)
+    };
+
+    // Differential cases have no snapshot of their own either
+    if !options.compare_harnesses.is_empty() {
+        return compare_harnesses(case, &options, params);
+    }
+
+    // Inline snapshots are checked right here; nothing is written or asked of git
+    if let Some(expected) = &options.expected {
+        let contents = run_harness(params)?;
+        return check_expected(harness, expected, &contents, case.float_epsilon, &options);
+    }
+
+    // Text-format harnesses write `.txt` instead of `.json`
+    let shared_path = snapshot_path(case.output_path, harness.format);
+    let git_root = manifest_dir
+        .ancestors()
+        .nth(case.manifest_depth)
+        .and_then(Path::to_str)
+        .ok_or_else(|| format!("cannot locate the git root above {:?}", manifest_dir))?;
+    if harness.keyed {
+        let contents = run_harness(params)?;
+        return run_keyed(case, harness, git_root, &contents);
+    }
+    // For `per_os` cases, the OS's own snapshot once there is one, and until
+    // then the shared snapshot if there is one of those
+    let os_path = options.per_os.then(|| os_snapshot_path(&shared_path));
+    let relative_path = match &os_path {
+        Some(os_path) if !in_git(git_root, os_path) && in_git(git_root, &shared_path) => {
+            shared_path
+        }
+        Some(os_path) => os_path.clone(),
+        None => shared_path,
+    };
+    let output_path = Path::new(git_root).join(&relative_path);
+
+    // With BLESS_INCREMENTAL, reuse the snapshot on disk if nothing it depends on changed.
+    // Files under a case's `cwd` can change without a rebuild, so those cases always run.
+    let incremental = Incremental::new(case.test_fn, case.harness, &params)
+        .filter(|_| !readonly() && options.cwd.is_none());
+    let contents = match &incremental {
+        Some(incremental) if incremental.is_fresh(&output_path) => {
+            eprintln!(
+                "Blessed test '{}': unchanged since last run, skipping harness.",
+                case.name
+            );
+            std::fs::read_to_string(&output_path).map_err(|e| {
+                format!(
+                    "Failed to read blessed output file '{:?}': {}",
+                    output_path, e
+                )
+            })?
+        }
+        _ if readonly() => apply_output_hooks(run_harness(params)?),
+        _ => {
+            let contents = apply_output_hooks(run_harness(params)?);
+
+            if let Some(parent) = output_path.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| {
+                    format!("Failed to create output directory '{:?}': {}", parent, e)
+                })?;
+            }
+            if history_enabled() {
+                history::archive_previous(&output_path, &contents)?;
+            }
+            std::fs::write(&output_path, &contents).map_err(|e| {
+                format!(
+                    "Failed to write blessed output file '{:?}': {}",
+                    output_path, e
+                )
+            })?;
+            if let Some(incremental) = &incremental {
+                incremental.record(&output_path);
+            }
+            contents
+        }
+    };
+
+    // Oversized snapshots are still written, so they can be inspected
+    let max_bytes = options
+        .max_snapshot_bytes
+        .unwrap_or(DEFAULT_MAX_SNAPSHOT_BYTES);
+    if let Some(problem) = size_problem(&relative_path, contents.len(), max_bytes) {
+        if options.deny_large_snapshots {
+            return Err(problem.into());
+        }
+        eprintln!("Blessed test '{}': warning: {}", case.name, problem);
+    }
+
+    if readonly() {
+        return check_readonly(harness, git_root, &relative_path, &contents, case, &options);
+    }
+
+    // Compare against a committed baseline instead of the index, if requested
+    if let Some(baseline_ref) = baseline_ref() {
+        let check = if harness.sensitive {
+            check_against_ref_redacted
+        } else {
+            check_against_ref
+        };
+        return check(git_root, &baseline_ref, &relative_path, &contents).map_err(Failure::from);
+    }
+
+    let status = git_status(git_root, &relative_path)
+        .map_err(|e| format!("Failed to get git status for '{}': {}", relative_path, e))?;
+    if status == GitStatus::Modified
+        && restore_if_equivalent(
+            git_root,
+            &relative_path,
+            &output_path,
+            &contents,
+            case.float_epsilon,
+            &options,
+        )
+    {
+        // Differences are within the case's float tolerance or ignored keys.
+    } else if status == GitStatus::Untracked && bless_mode() != Bless::None {
+        git::git_add(git_root, &relative_path)?;
+        eprintln!(
+            "Blessed test '{}': new snapshot '{}' added to the index.",
+            case.name, relative_path
+        );
+    } else if status == GitStatus::Modified && bless_mode() == Bless::All {
+        git::git_add(git_root, &relative_path)?;
+        eprintln!(
+            "Blessed test '{}': changed snapshot '{}' added to the index; review it with `git diff --cached`.",
+            case.name, relative_path
+        );
+    } else if status == GitStatus::Untracked && options.new {
+        eprintln!(
+            "Blessed test '{}': new snapshot '{}' written; `git add` it and remove `new` from the case.",
+            case.name, relative_path
+        );
+    } else if let Some(problem) = status_problem(&status, &relative_path) {
+        let indexed = git::show_file_at_ref(git_root, "", &relative_path)
+            .ok()
+            .flatten();
+        let context = verbose_params
+            .map(|params| failure_context(&params, indexed.as_deref(), &contents))
+            .unwrap_or_default();
+        if status != GitStatus::Modified {
+            return Err(format!("{}{}", problem, context).into());
+        }
+        let diff = indexed
+            .filter(|_| !harness.sensitive)
+            .map(|indexed| unified_diff(&indexed, &contents, context_lines()));
+        let message =
+            match committed_snapshot_problem(git_root, &relative_path, harness.format, &contents)
+                .or_else(|| format_version_problem(git_root, &relative_path, harness))
+            {
+                Some(problem) => problem,
+                None if harness.sensitive => format!("{}\n(diff {})", problem, REDACTED),
+                None => format!(
+                    "{}{}",
+                    problem,
+                    describe_modification(git_root, &relative_path, &contents)
+                ),
+            };
+        let message = match os_path.filter(|os_path| *os_path != relative_path) {
+            Some(os_path) => format!(
+                "{}\nIf the output legitimately differs on {}, copy '{}' to '{}' and `git add` that, then `git checkout` the shared snapshot.",
+                message,
+                std::env::consts::OS,
+                relative_path,
+                os_path
+            ),
+            None => message,
+        };
+        return Err(Failure {
+            message: message + &context,
+            diff,
+        });
+    }
+
+    // Derive the compact copy from the snapshot as it now stands on disk
+    if case.compact_copy && harness.format == SnapshotFormat::Json {
+        write_compact_copy(&output_path)?;
+        let compact_relative_path = compact_path(Path::new(&relative_path))
+            .to_string_lossy()
+            .to_string();
+        let status = git_status(git_root, &compact_relative_path).map_err(|e| {
+            format!(
+                "Failed to get git status for '{}': {}",
+                compact_relative_path, e
+            )
+        })?;
+        let bless = match status {
+            GitStatus::Untracked => bless_mode() != Bless::None,
+            GitStatus::Modified => bless_mode() == Bless::All,
+            _ => false,
+        };
+        if bless {
+            git::git_add(git_root, &compact_relative_path)?;
+        } else if let Some(problem) = status_problem(&status, &compact_relative_path) {
+            return Err(problem.into());
+        }
+    }
+    Ok(())
+}
+
+// Runs every harness of a `compare_harnesses` case on the same params, and
+// fails with a diff against the first for any that renders differently
+fn compare_harnesses(case: &CaseInfo, options: &CaseOptions, params: Value) -> Result<(), Failure> {
+    let mut rendered = Vec::new();
+    for name in &options.compare_harnesses {
+        let harness = find_harness(name)?;
+        if !harness.takes_params && !params.is_null() {
+            return Err(format!(
+                "harness '{}' takes no parameters, but params were provided",
+                harness.name
+            )
+            .into());
+        }
+        take_warnings();
+        let result = invoke(case, harness, options, params.clone());
+        let contents = render_snapshot(harness, result, take_warnings(), options)?;
+        rendered.push((harness, contents));
+    }
+    let (first, expected) = &rendered[0];
+    for (harness, contents) in &rendered[1..] {
+        if contents == expected {
+            continue;
+        }
+        let problem = format!(
+            "harness '{}' differs from harness '{}' on the same params",
+            harness.name, first.name
+        );
+        if first.sensitive || harness.sensitive {
+            return Err(format!("{}: {}", problem, REDACTED).into());
+        }
+        let diff = unified_diff(expected, contents, context_lines());
+        return Err(Failure {
+            message: format!("{}:\n{}", problem, diff),
+            diff: Some(diff),
+        });
+    }
+    Ok(())
+}
+
+// Merges a `#[harness(keyed)]` case's output into its harness's keyed file,
+// then checks just this case's entry, against the baseline ref if one is set
+fn run_keyed(
+    case: &CaseInfo,
+    harness: &HarnessFn,
+    git_root: &str,
+    contents: &str,
+) -> Result<(), Failure> {
+    let relative_path = keyed_path(case.output_path, harness);
+    let params: Value = serde_json::from_str(case.params)
+        .expect("Internal error: Failed to re-parse params JSON string");
+    let key = input_key(&params);
+    let value = keyed::entry(harness.format, contents)?;
+    if !readonly() {
+        keyed::merge_entry(
+            &Path::new(git_root).join(&relative_path),
+            &key,
+            value.clone(),
+        )?;
+    }
+    let git_ref = baseline_ref().unwrap_or_default();
+    let checked = keyed::check_entry(harness, git_root, &git_ref, &relative_path, &key, &value);
+    if checked.is_err() && git_ref.is_empty() && !readonly() && bless_mode() == Bless::All {
+        keyed::stage(git_root, &relative_path)?;
+        eprintln!(
+            "Blessed test '{}': entry for input {:?} in '{}' added to the index; review it with `git diff --cached`.",
+            case.name, key, relative_path
+        );
+        return Ok(());
+    }
+    checked
+}
+
+// With BLESS_READONLY, nothing was written: the rendered snapshot is compared
+// in memory with the staged one (or the one at BLESS_BASELINE_REF), allowing
+// the case's float and text tolerances as a clean `git status` would
+fn check_readonly(
+    harness: &HarnessFn,
+    git_root: &str,
+    relative_path: &str,
+    contents: &str,
+    case: &CaseInfo,
+    options: &CaseOptions,
+) -> Result<(), Failure> {
+    let git_ref = baseline_ref().unwrap_or_else(|| STAGED_REF.to_string());
+    let baseline = read_baseline(git_root, &git_ref, relative_path)?;
+    if baseline == contents || tolerated(&baseline, contents, case.float_epsilon, options).is_some()
+    {
+        return Ok(());
+    }
+    let problem = format!(
+        "File '{}' differs from {}. Nothing was written (BLESS_READONLY); rerun without it to update the snapshot.",
+        relative_path,
+        baseline_name(&git_ref)
+    );
+    if harness.sensitive {
+        return Err(format!("{}\n(diff {})", problem, REDACTED).into());
+    }
+    let diff = unified_diff(&baseline, contents, context_lines());
+    Err(Failure {
+        message: format!("{}\n{}", problem, diff),
+        diff: Some(diff),
+    })
+}
+
+// Compares the rendered snapshot with a case's inline `expected` output,
+// which is written the way the snapshot file would read
+fn check_expected(
+    harness: &HarnessFn,
+    expected: &Value,
+    contents: &str,
+    float_epsilon: Option<f64>,
+    options: &CaseOptions,
+) -> Result<(), Failure> {
+    let (expected, matches) = match harness.format {
+        SnapshotFormat::Ndjson => {
+            let mut actual = parse_ndjson(contents)
+                .map_err(|e| format!("Failed to re-parse the rendered snapshot: {}", e))?;
+            let Value::Array(items) = expected else {
+                return Err(format!(
+                    "harness '{}' writes NDJSON, so `expected` must be an array",
+                    harness.name
+                )
+                .into());
+            };
+            let mut wanted = expected.clone();
+            compare::remove_keys(&mut actual, &options.ignore_keys);
+            compare::remove_keys(&mut wanted, &options.ignore_keys);
+            let matches = match float_epsilon {
+                Some(epsilon) => approx_eq(&wanted, &actual, epsilon),
+                None => wanted == actual,
+            };
+            // Shown the way the snapshot file would read
+            let expected = items
+                .iter()
+                .map(|item| format!("{}\n", crate::Value::new(item.clone())))
+                .collect();
+            (expected, matches)
+        }
+        SnapshotFormat::Json => {
+            let mut actual: Value = serde_json::from_str(contents)
+                .map_err(|e| format!("Failed to re-parse the rendered snapshot: {}", e))?;
+            let mut wanted = expected.clone();
+            compare::remove_keys(&mut actual, &options.ignore_keys);
+            compare::remove_keys(&mut wanted, &options.ignore_keys);
+            let expected = serde_json::to_string_pretty(expected)
+                .map_err(|e| format!("Failed to serialize expected output: {}", e))?;
+            let matches = match float_epsilon {
+                Some(epsilon) => approx_eq(&wanted, &actual, epsilon),
+                None => wanted == actual,
+            } || (options.ignore_case
+                && compare::texts_match(&expected, contents, false, true));
+            (expected, matches)
+        }
+        SnapshotFormat::Text => {
+            let Value::String(expected) = expected else {
+                return Err(format!(
+                    "harness '{}' writes text snapshots, so `expected` must be a string",
+                    harness.name
+                )
+                .into());
+            };
+            // A trailing newline is a file convention, not part of the output
+            let matches = expected.trim_end_matches('\n') == contents.trim_end_matches('\n')
+                || compare::texts_match(
+                    expected,
+                    contents,
+                    options.loose_whitespace,
+                    options.ignore_case,
+                );
+            (expected.clone(), matches)
+        }
+    };
+    if matches {
+        return Ok(());
+    }
+    if harness.sensitive {
+        return Err(format!("output differs from `expected`: {}", REDACTED).into());
+    }
+    let diff = unified_diff(&expected, contents, context_lines());
+    Err(Failure {
+        message: format!("output differs from `expected`:\n{}", diff),
+        diff: Some(diff),
+    })
+}
+
+// Which snapshots a run stages with `git add` instead of failing on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bless {
+    None,
+    // `BLESS=new`: snapshots that aren't in git yet; changes to existing
+    // snapshots still need review
+    New,
+    // `BLESS=1` (any value but `0` and `new`, or `BLESSED_UPDATE=1`): new
+    // and changed snapshots
+    All,
+}
+
+fn bless_mode() -> Bless {
+    match std::env::var_os("BLESS") {
+        Some(value) if value == "new" => Bless::New,
+        Some(value) if value != "0" => Bless::All,
+        _ if std::env::var_os("BLESSED_UPDATE").is_some_and(|value| value != "0") => Bless::All,
+        _ => Bless::None,
+    }
+}
+
+// Whether git has a version of the file, committed or staged
+fn in_git(git_root: &str, relative_path: &str) -> bool {
+    matches!(
+        git::show_bytes_at_ref(git_root, "", relative_path),
+        Ok(Some(_))
+    )
+}
+
+// A committed snapshot that isn't valid UTF-8 (or, for JSON and NDJSON
+// harnesses, valid JSON) was corrupted or botched by hand; saying so beats a confusing diff
+fn committed_snapshot_problem(
+    git_root: &str,
+    relative_path: &str,
+    format: SnapshotFormat,
+    contents: &str,
+) -> Option<String> {
+    let committed = git::show_bytes_at_ref(git_root, "", relative_path).ok()??;
+    let remedy = "Review the new output and `git add` it to replace the corrupted snapshot.";
+    let committed = match String::from_utf8(committed) {
+        Ok(committed) => committed,
+        Err(e) => {
+            return Some(format!(
+                "Committed snapshot '{}' is not valid UTF-8 ({}). {}",
+                relative_path,
+                e.utf8_error(),
+                remedy
+            ))
+        }
+    };
+    let parse = |text: &str| match format {
+        SnapshotFormat::Json => serde_json::from_str::<Value>(text)
+            .map(drop)
+            .map_err(|e| e.to_string()),
+        SnapshotFormat::Ndjson => parse_ndjson(text).map(drop),
+        SnapshotFormat::Text => Ok(()),
+    };
+    // An output hook may wrap the snapshot in something else, such as a
+    // header; then the new output doesn't parse either, and neither file is
+    // expected to
+    if parse(contents).is_err() {
+        return None;
+    }
+    if let Err(e) = parse(&committed) {
+        return Some(format!(
+            "Committed snapshot '{}' is not valid {} ({}). {}",
+            relative_path,
+            if format == SnapshotFormat::Ndjson {
+                "NDJSON"
+            } else {
+                "JSON"
+            },
+            e,
+            remedy
+        ));
+    }
+    None
+}
+
+// Whether libtest is showing test output (`cargo test -- --nocapture`), in
+// which case failures come with a detailed report
+fn verbose_failures() -> bool {
+    std::env::args().any(|arg| arg == "--nocapture" || arg == "--no-capture")
+        || std::env::var_os("RUST_TEST_NOCAPTURE").is_some_and(|value| value != "0")
+}
+
+const CONTEXT_LINES: usize = 40;
+const CONTEXT_COLUMN_WIDTH: usize = 60;
+
+// The case's params, then the start of the committed and new snapshots side
+// by side, with differing lines marked `*`
+fn failure_context(params: &Value, indexed: Option<&str>, contents: &str) -> String {
+    let params = serde_json::to_string_pretty(params).unwrap_or_else(|e| e.to_string());
+    let mut context = format!("\nParams:\n{}\n", params);
+    let old: Vec<&str> = indexed.unwrap_or_default().lines().collect();
+    let new: Vec<&str> = contents.lines().collect();
+    let total = old.len().max(new.len());
+    context.push_str(&format!(
+        "{:<width$}   {}\n",
+        "committed",
+        "new",
+        width = CONTEXT_COLUMN_WIDTH
+    ));
+    for i in 0..total.min(CONTEXT_LINES) {
+        let (old_line, new_line) = (old.get(i), new.get(i));
+        let marker = if old_line == new_line { '|' } else { '*' };
+        context.push_str(&format!(
+            "{:<width$} {} {}\n",
+            column(old_line.copied().unwrap_or_default()),
+            marker,
+            column(new_line.copied().unwrap_or_default()),
+            width = CONTEXT_COLUMN_WIDTH
+        ));
+    }
+    if total > CONTEXT_LINES {
+        context.push_str(&format!("(first {} of {} lines)\n", CONTEXT_LINES, total));
+    }
+    context
+}
+
+fn column(line: &str) -> String {
+    if line.chars().count() <= CONTEXT_COLUMN_WIDTH {
+        return line.to_string();
+    }
+    let mut truncated: String = line.chars().take(CONTEXT_COLUMN_WIDTH - 1).collect();
+    truncated.push('…');
+    truncated
+}
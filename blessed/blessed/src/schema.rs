@@ -0,0 +1,161 @@
+// Uses of the JSON Schema of harness output types, behind the `schema`
+// feature: validating output (`#[harness(schema)]`) and reporting which enum
+// variants the snapshots exercise
+
+use crate::{CaseInfo, FORMAT_VERSION_KEY};
+use schemars::JsonSchema;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Checks that `output`, the serialized output of a harness, conforms to the
+/// JSON Schema of `T`, the harness's return type. Catches hand-written
+/// `Serialize` impls producing something the type doesn't describe.
+pub fn validate_output<T: JsonSchema>(output: &Value) -> Result<(), String> {
+    let schema = serde_json::to_value(schemars::schema_for!(T))
+        .map_err(|e| format!("Failed to serialize the output schema: {}", e))?;
+    let validator = jsonschema::validator_for(&schema)
+        .map_err(|e| format!("Invalid output schema for `{}`: {}", T::schema_name(), e))?;
+    let problems: Vec<String> = validator
+        .iter_errors(output)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+            let path = if path.is_empty() {
+                "/".to_string()
+            } else {
+                path
+            };
+            format!("at {}: {}", path, error)
+        })
+        .collect();
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "Output does not match the schema of `{}`:\n{}",
+        T::schema_name(),
+        problems.join("\n")
+    ))
+}
+
+/// Which variants of the enums in a harness's output type its snapshots
+/// contain, as returned by [`variant_coverage`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VariantCoverage {
+    /// Keyed by enum name (its schema name, e.g. `Regex`).
+    pub enums: BTreeMap<String, EnumCoverage>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnumCoverage {
+    pub seen: BTreeSet<String>,
+    pub missing: BTreeSet<String>,
+}
+
+impl fmt::Display for VariantCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, coverage) in &self.enums {
+            let total = coverage.seen.len() + coverage.missing.len();
+            write!(
+                f,
+                "{}: {}/{} variants seen",
+                name,
+                coverage.seen.len(),
+                total
+            )?;
+            if !coverage.missing.is_empty() {
+                let missing: Vec<&str> = coverage.missing.iter().map(String::as_str).collect();
+                write!(f, "; missing {}", missing.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the snapshots of every case of `harness` and reports, for each enum
+/// declared in the schema of `T` (the harness's return type), which variants
+/// appear in them, to spot untested branches.
+///
+/// Variants are recognized by serde's default external tagging: a struct,
+/// tuple or newtype variant as a single-key object, a unit variant as a
+/// string. Cases without a snapshot file (e.g. inline `expected`) are skipped.
+pub fn variant_coverage<T: JsonSchema>(harness: &str) -> Result<VariantCoverage, String> {
+    let schema = serde_json::to_value(schemars::schema_for!(T))
+        .map_err(|e| format!("Failed to serialize the output schema: {}", e))?;
+    let mut declared = BTreeMap::new();
+    if let Some(variants) = enum_variants(&schema) {
+        declared.insert(T::schema_name().to_string(), variants);
+    }
+    for (name, definition) in schema["$defs"].as_object().into_iter().flatten() {
+        if let Some(variants) = enum_variants(definition) {
+            declared.insert(name.clone(), variants);
+        }
+    }
+
+    let mut seen = BTreeSet::new();
+    for case in inventory::iter::<CaseInfo> {
+        if !crate::find_harness(case.harness).is_ok_and(|h| h.is_named(harness)) {
+            continue;
+        }
+        let Some(path) = case.snapshot_file() else {
+            continue;
+        };
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        if let Ok(mut snapshot) = serde_json::from_str(&contents) {
+            // Keys blessed adds to the output would hide a top-level enum's tag
+            crate::compare::remove_keys(
+                &mut snapshot,
+                &[FORMAT_VERSION_KEY.to_string(), "warnings".to_string()],
+            );
+            collect_tags(&snapshot, &mut seen);
+        }
+    }
+
+    let enums = declared
+        .into_iter()
+        .map(|(name, variants)| {
+            let (seen, missing) = variants.into_iter().partition(|v| seen.contains(v));
+            (name, EnumCoverage { seen, missing })
+        })
+        .collect();
+    Ok(VariantCoverage { enums })
+}
+
+// The variant names of an externally tagged enum's schema: a `oneOf` whose
+// alternatives are all single-property objects or string constants
+fn enum_variants(schema: &Value) -> Option<BTreeSet<String>> {
+    let alternatives = schema["oneOf"].as_array()?;
+    let mut variants = BTreeSet::new();
+    for alternative in alternatives {
+        if let Some(name) = alternative["const"].as_str() {
+            variants.insert(name.to_string());
+        } else if let Some(names) = alternative["enum"].as_array() {
+            variants.extend(names.iter().filter_map(Value::as_str).map(str::to_string));
+        } else {
+            match alternative["required"].as_array().map(Vec::as_slice) {
+                Some([Value::String(name)]) => variants.insert(name.clone()),
+                _ => return None,
+            };
+        }
+    }
+    Some(variants)
+}
+
+fn collect_tags(value: &Value, seen: &mut BTreeSet<String>) {
+    match value {
+        Value::String(s) => {
+            seen.insert(s.clone());
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_tags(item, seen)),
+        Value::Object(map) => {
+            if map.len() == 1 {
+                seen.extend(map.keys().cloned());
+            }
+            map.values().for_each(|child| collect_tags(child, seen));
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,436 @@
+// Turning a harness result into the contents of its snapshot file
+
+use crate::finite::take_enum_pointers;
+use crate::{attach_warnings, redact, serialize_output, Fuzz, HarnessFn, Redaction};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// How a harness's output is stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// Pretty-printed JSON in `{name}.json`.
+    Json,
+    /// The harness output (a JSON string) written verbatim to `{name}.txt`,
+    /// e.g. a `#[harness(text)]` report or `#[harness(debug)]`'s `{:#?}`
+    /// rendering.
+    Text,
+    /// A `#[harness(ndjson)]` harness's sequence output in `{name}.ndjson`,
+    /// one element per line as compact JSON with sorted keys, so a changed
+    /// element changes one line.
+    Ndjson,
+}
+
+impl SnapshotFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            SnapshotFormat::Json => "json",
+            SnapshotFormat::Text => "txt",
+            SnapshotFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Swaps the `.json` extension of a snapshot path for the one `format` uses.
+pub fn snapshot_path(json_path: &str, format: SnapshotFormat) -> String {
+    format!(
+        "{}.{}",
+        json_path.strip_suffix(".json").unwrap_or(json_path),
+        format.extension()
+    )
+}
+
+/// Where the compact copy of the snapshot at `output_path` goes: `name.min.json`.
+pub fn compact_path(output_path: &Path) -> PathBuf {
+    output_path.with_extension("min.json")
+}
+
+/// Writes the compact copy of the pretty snapshot at `output_path`, parsed
+/// from that very file so the two can never disagree.
+pub fn write_compact_copy(output_path: &Path) -> Result<(), String> {
+    let pretty = std::fs::read_to_string(output_path).map_err(|e| {
+        format!(
+            "Failed to read blessed output file '{:?}': {}",
+            output_path, e
+        )
+    })?;
+    let value: Value = serde_json::from_str(&pretty)
+        .map_err(|e| format!("Snapshot '{:?}' is not valid JSON: {}", output_path, e))?;
+    let compact_path = compact_path(output_path);
+    std::fs::write(&compact_path, value.to_string()).map_err(|e| {
+        format!(
+            "Failed to write blessed output file '{:?}': {}",
+            compact_path, e
+        )
+    })
+}
+
+/// Per-case settings from a definition file that apply at runtime, embedded
+/// by `tests!` as JSON.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaseOptions {
+    /// Rules replacing volatile values in the output.
+    pub redact: Vec<Redaction>,
+    /// JSON pointer selecting the part of the output to snapshot.
+    pub project: Option<String>,
+    /// Directory the harness resolves relative paths against, relative to
+    /// the crate root.
+    pub cwd: Option<String>,
+    /// Set by `new: true`: an untracked snapshot is expected rather than a
+    /// failure.
+    pub new: bool,
+    /// Top-level keys whose changes don't fail the case.
+    pub ignore_keys: Vec<String>,
+    /// Run the harness over generated inputs instead of `params`.
+    pub fuzz: Option<Fuzz>,
+    /// Set by `tests!(enum_tag = "...")`: externally tagged enums are
+    /// rewritten to carry their variant under this key.
+    pub enum_tag: Option<String>,
+    /// Seed for harnesses using randomness, read with `current_seed()`.
+    pub seed: Option<u64>,
+    /// Set by `quarantine: true`: failures are reported but don't fail the
+    /// generated test.
+    pub quarantine: bool,
+    /// Set by `loose_whitespace: true`: changes only to trailing whitespace
+    /// or blank lines don't fail the case.
+    pub loose_whitespace: bool,
+    /// Set by `ignore_case: true`: changes only to letter case don't fail
+    /// the case, and the new casing is kept on disk.
+    pub ignore_case: bool,
+    /// Set by `per_os: true`: the snapshot is `{case}.{os}.json`, or the
+    /// shared `{case}.json` while the former isn't committed.
+    pub per_os: bool,
+    /// The snapshot given inline in the definition file, compared against
+    /// directly instead of a file checked by git.
+    #[serde(deserialize_with = "present")]
+    pub expected: Option<Value>,
+    /// Set by `compare_harnesses`: the harnesses run on the params, which
+    /// pass when they all render the same snapshot. Nothing is written.
+    pub compare_harnesses: Vec<String>,
+    /// Set by `tests!(max_snapshot_bytes = N)`: the size above which a
+    /// snapshot is reported, [`DEFAULT_MAX_SNAPSHOT_BYTES`] if unset.
+    pub max_snapshot_bytes: Option<u64>,
+    /// Set by `tests!(deny_large_snapshots)`: a snapshot over the size limit
+    /// fails the case instead of printing a warning.
+    pub deny_large_snapshots: bool,
+}
+
+// `null` counts as present, since it is a valid expected output
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+impl CaseOptions {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid case options: {}", e))
+    }
+}
+
+/// Renders a harness result as the snapshot file contents: the output is
+/// projected, redacted, has its line endings and numbers normalized if the
+/// harness asks for it, and gets any warnings and the harness's format
+/// version attached. Framework errors are snapshotted as `blessed_error`
+/// rather than failing the test.
+pub fn render_snapshot(
+    harness: &HarnessFn,
+    result: Result<Value, String>,
+    warnings: Vec<String>,
+    options: &CaseOptions,
+) -> Result<String, String> {
+    let format = harness.format;
+    // Recorded when the harness's output was serialized, on this thread
+    let enums = take_enum_pointers();
+    let mut output = match (result, format) {
+        (Ok(mut value), SnapshotFormat::Json | SnapshotFormat::Ndjson) => {
+            if let Some(tag) = &options.enum_tag {
+                retag_enums_at(&mut value, tag, &enums);
+            }
+            project(value, options.project.as_deref())?
+        }
+        (Ok(value), SnapshotFormat::Text) => project(value, options.project.as_deref())?,
+        (Err(e), SnapshotFormat::Json) => serde_json::json!({ "blessed_error": e }),
+        (Err(e), SnapshotFormat::Text) => Value::String(format!("blessed_error: {}", e)),
+        (Err(e), SnapshotFormat::Ndjson) => serde_json::json!([{ "blessed_error": e }]),
+    };
+    redact(&mut output, &options.redact)?;
+    if harness.normalize_newlines {
+        normalize_newlines(&mut output);
+    }
+    if harness.canonical_numbers {
+        canonicalize_numbers(&mut output);
+    }
+
+    match format {
+        SnapshotFormat::Json => {
+            let output = attach_warnings(output, warnings);
+            let output = attach_format_version(output, harness.format_version);
+            serde_json::to_string_pretty(&output)
+                .map_err(|e| format!("Failed to serialize result to JSON: {}", e))
+        }
+        SnapshotFormat::Text => {
+            let mut text = match output {
+                Value::String(text) => text,
+                other => serde_json::to_string_pretty(&other)
+                    .map_err(|e| format!("Failed to serialize result to JSON: {}", e))?,
+            };
+            if (harness.trailing_newline || !warnings.is_empty()) && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            for warning in warnings {
+                text.push_str(&format!("warning: {}\n", warning));
+            }
+            Ok(text)
+        }
+        SnapshotFormat::Ndjson => {
+            let Value::Array(items) = output else {
+                return Err(format!(
+                    "harness '{}' writes NDJSON, so its output must be a sequence, not {}",
+                    harness.name,
+                    json_type(&output)
+                ));
+            };
+            let mut lines: String = items
+                .into_iter()
+                .map(|item| format!("{}\n", crate::Value::new(item)))
+                .collect();
+            if !warnings.is_empty() {
+                lines.push_str(&format!(
+                    "{}\n",
+                    serde_json::json!({ "warnings": warnings })
+                ));
+            }
+            Ok(lines)
+        }
+    }
+}
+
+/// Reads an NDJSON snapshot back into the array it was written from.
+pub fn parse_ndjson(contents: &str) -> Result<Value, String> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| format!("line {} is not JSON: {}", i + 1, e))
+        })
+        .collect::<Result<Vec<Value>, String>>()
+        .map(Value::Array)
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// The snapshot path for the current OS, e.g. `blessed/case.windows.json`
+/// for `blessed/case.json` on Windows, using the names of
+/// `std::env::consts::OS`.
+pub fn os_snapshot_path(path: &str) -> String {
+    let (stem, extension) = path
+        .rsplit_once('.')
+        .expect("snapshot paths have an extension");
+    format!("{}.{}.{}", stem, std::env::consts::OS, extension)
+}
+
+/// Snapshots larger than this many bytes (1 MiB) are reported, unless
+/// `tests!(max_snapshot_bytes = N)` sets another limit.
+pub const DEFAULT_MAX_SNAPSHOT_BYTES: u64 = 1 << 20;
+
+/// Describes a snapshot of `size` bytes that is over `max_bytes`, with ways
+/// to trim it, or `None` if it fits.
+pub fn size_problem(relative_path: &str, size: usize, max_bytes: u64) -> Option<String> {
+    (size as u64 > max_bytes).then(|| {
+        format!(
+            "Snapshot '{}' is {} bytes, over the limit of {} bytes. Consider `project` to \
+             snapshot only part of the output, or `redact` to drop bulky values.",
+            relative_path, size, max_bytes
+        )
+    })
+}
+
+/// Key recording `#[harness(format_version = N)]` in JSON snapshots.
+pub const FORMAT_VERSION_KEY: &str = "_format";
+
+// Like warnings: object outputs gain the key, anything else gets wrapped
+fn attach_format_version(output: Value, format_version: Option<u32>) -> Value {
+    let Some(version) = format_version else {
+        return output;
+    };
+    match output {
+        Value::Object(mut map) if !map.contains_key(FORMAT_VERSION_KEY) => {
+            map.insert(FORMAT_VERSION_KEY.to_string(), version.into());
+            Value::Object(map)
+        }
+        output => serde_json::json!({ FORMAT_VERSION_KEY: version, "output": output }),
+    }
+}
+
+/// Explains a failure caused by the harness's format version differing from
+/// the one recorded in the committed snapshot `indexed`, if that's the case.
+pub(crate) fn format_version_problem(
+    indexed: &str,
+    relative_path: &str,
+    format_version: Option<u32>,
+) -> Option<String> {
+    let committed = serde_json::from_str::<Value>(indexed)
+        .ok()?
+        .get(FORMAT_VERSION_KEY)
+        .and_then(Value::as_u64);
+    if committed == format_version.map(u64::from) {
+        return None;
+    }
+    let describe = |version: Option<u64>| match version {
+        Some(version) => format!("version {}", version),
+        None => "no version".to_string(),
+    };
+    Some(format!(
+        "File '{}' was committed with snapshot format {}, but the harness now produces {}. The format changed intentionally, so review and re-bless the snapshot with `git add`.",
+        relative_path,
+        describe(committed),
+        describe(format_version.map(u64::from)),
+    ))
+}
+
+/// Serializes `output` like a harness's, with its externally tagged enum
+/// values, as serde writes them by default, rewritten to carry their variant
+/// under `tag`: `{"Variant": {"field": 1}}` becomes
+/// `{"tag": "Variant", "field": 1}`, and a variant with non-object content,
+/// `{"Variant": "x"}`, becomes `{"tag": "Variant", "content": "x"}`.
+///
+/// Only values serde serialized as enum variants are rewritten; a map with a
+/// single entry stays as it is.
+pub fn retag_enums<T: Serialize>(output: T, tag: &str) -> Result<Value, String> {
+    let mut value = serialize_output(output)?;
+    retag_enums_at(&mut value, tag, &take_enum_pointers());
+    Ok(value)
+}
+
+// Retags the enum values at `pointers`, which must be ordered innermost first
+// so that rewriting a value doesn't move the ones inside it
+fn retag_enums_at(value: &mut Value, tag: &str, pointers: &[String]) {
+    for pointer in pointers {
+        let Some(Value::Object(map)) = value.pointer_mut(pointer) else {
+            continue;
+        };
+        let Some(variant) = map.keys().next().filter(|_| map.len() == 1).cloned() else {
+            continue;
+        };
+        let content = map.remove(&variant).expect("checked above");
+        let mut retagged = serde_json::Map::new();
+        retagged.insert(tag.to_string(), Value::String(variant));
+        match content {
+            Value::Object(fields) if !fields.contains_key(tag) => retagged.extend(fields),
+            content => {
+                retagged.insert("content".to_string(), content);
+            }
+        }
+        *map = retagged;
+    }
+}
+
+/// Rewrites `\r\n` to `\n` in every string in `value`. Object keys are left
+/// as they are.
+pub fn normalize_newlines(value: &mut Value) {
+    match value {
+        Value::String(text) if text.contains("\r\n") => *text = text.replace("\r\n", "\n"),
+        Value::Array(items) => items.iter_mut().for_each(normalize_newlines),
+        Value::Object(map) => map.values_mut().for_each(normalize_newlines),
+        _ => {}
+    }
+}
+
+// Integers up to this magnitude are exactly representable as `f64`
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Rewrites every float in `value` that has an integral value as an integer,
+/// so `1.0` and `1` (and `-0.0` and `0`) serialize the same. Other floats
+/// already serialize in their shortest round-trippable form.
+pub fn canonicalize_numbers(value: &mut Value) {
+    match value {
+        Value::Number(number) => {
+            if let Some(float) = number.as_f64().filter(|_| number.is_f64()) {
+                if float.fract() == 0.0 && float.abs() <= MAX_EXACT_INTEGER {
+                    *value = Value::from(float as i64);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(canonicalize_numbers),
+        Value::Object(map) => map.values_mut().for_each(canonicalize_numbers),
+        _ => {}
+    }
+}
+
+fn project(output: Value, pointer: Option<&str>) -> Result<Value, String> {
+    let Some(pointer) = pointer else {
+        return Ok(output);
+    };
+    match output.pointer(pointer) {
+        Some(projected) => Ok(projected.clone()),
+        None => Err(format!(
+            "`project` pointer '{}' does not resolve in the harness output",
+            pointer
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_entry_maps_are_not_retagged() {
+        #[derive(Serialize)]
+        enum Shape {
+            Circle { r: u32 },
+        }
+        let counts = std::collections::BTreeMap::from([("Alice", 3)]);
+        let output = retag_enums((&counts, Shape::Circle { r: 1 }), "type").unwrap();
+        assert_eq!(
+            output,
+            serde_json::json!([{ "Alice": 3 }, { "type": "Circle", "r": 1 }])
+        );
+    }
+
+    #[test]
+    fn os_snapshots_are_named_after_the_os() {
+        assert_eq!(
+            os_snapshot_path("blessed/happy.json"),
+            format!("blessed/happy.{}.json", std::env::consts::OS)
+        );
+    }
+
+    #[test]
+    fn oversized_snapshots_are_described() {
+        assert_eq!(size_problem("blessed/a.json", 10, 10), None);
+        assert_eq!(
+            size_problem("blessed/a.json", 11, 10).as_deref(),
+            Some(
+                "Snapshot 'blessed/a.json' is 11 bytes, over the limit of 10 bytes. Consider \
+                 `project` to snapshot only part of the output, or `redact` to drop bulky values."
+            )
+        );
+    }
+
+    #[test]
+    fn newlines_are_normalized_in_strings_only() {
+        let mut output = serde_json::json!({ "a\r\nb": ["x\r\ny\r\n", "\r"], "n": 1 });
+        normalize_newlines(&mut output);
+        assert_eq!(
+            output,
+            serde_json::json!({ "a\r\nb": ["x\ny\n", "\r"], "n": 1 })
+        );
+    }
+
+    #[test]
+    fn integral_floats_become_integers() {
+        let mut output = serde_json::json!([1.0, -0.0, 1.5, 3, 1e300, { "n": 2.0 }]);
+        canonicalize_numbers(&mut output);
+        assert_eq!(output.to_string(), r#"[1,0,1.5,3,1e300,{"n":2}]"#);
+    }
+}
@@ -0,0 +1,84 @@
+// Finding debris in the snapshot directory (`tests!(deny_stray_snapshots)`)
+//
+// A file under `blessed/` that git doesn't track and no current case writes
+// is most likely left over from a deleted or renamed case, or from a manual
+// experiment. Tracked files are never stray: removing a committed snapshot is
+// a reviewed change like any other.
+
+use crate::{
+    compact_path, find_harness, keyed_path, os_snapshot_path, snapshot_path, CaseInfo, CaseOptions,
+    SnapshotFormat,
+};
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Command;
+
+/// Untracked files under `blessed/` in the crate at `manifest_dir` that no
+/// discovered case writes, relative to the git root and sorted. Files git
+/// ignores are exempt, so `.gitignore` doubles as the allowlist; so are
+/// `history/` archives (`BLESS_HISTORY`), `REPORT.md` (`tests!(report)`)
+/// and `.gitattributes` (`tests!(gitattributes)`).
+pub fn stray_snapshots(manifest_dir: &str) -> Result<Vec<String>, String> {
+    let output = Command::new("git")
+        .args([
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+            "--full-name",
+            "--",
+            "blessed",
+        ])
+        .current_dir(manifest_dir)
+        .output()
+        .map_err(|e| format!("Failed to execute git ls-files: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "`git ls-files` failed (exit code: {}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let written = written_snapshots(manifest_dir);
+    let mut stray: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|path| !written.contains(*path))
+        .filter(|path| {
+            !path.contains("/history/")
+                && !path.ends_with("/REPORT.md")
+                && !path.ends_with("/.gitattributes")
+        })
+        .map(str::to_string)
+        .collect();
+    stray.sort();
+    Ok(stray)
+}
+
+// Every file the crate's cases can write, relative to the git root
+fn written_snapshots(manifest_dir: &str) -> HashSet<String> {
+    let mut written = HashSet::new();
+    let cases = inventory::iter::<CaseInfo>
+        .into_iter()
+        .filter(|case| Path::new(case.manifest_dir) == Path::new(manifest_dir));
+    for case in cases {
+        let paths = match find_harness(case.harness) {
+            Ok(harness) if harness.keyed => vec![keyed_path(case.output_path, harness)],
+            Ok(harness) => vec![snapshot_path(case.output_path, harness.format)],
+            // Unknown here, so either format could have been written
+            Err(_) => vec![
+                snapshot_path(case.output_path, SnapshotFormat::Json),
+                snapshot_path(case.output_path, SnapshotFormat::Text),
+                snapshot_path(case.output_path, SnapshotFormat::Ndjson),
+            ],
+        };
+        let per_os = CaseOptions::from_json(case.options).is_ok_and(|options| options.per_os);
+        for path in paths {
+            if per_os {
+                written.insert(os_snapshot_path(&path));
+            }
+            written.insert(compact_path(Path::new(&path)).to_string_lossy().to_string());
+            written.insert(path);
+        }
+    }
+    written
+}
@@ -0,0 +1,55 @@
+// Structural comparison of snapshots: which paths exist, and with what types
+
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Paths and types present in only one of `old` and `new`, as
+/// `(removed, added)`. Array elements share the path segment `*`, so only
+/// shape changes are reported, not value changes.
+pub fn structure_diff(old: &Value, new: &Value) -> (Vec<String>, Vec<String>) {
+    let old_shape = shape(old);
+    let new_shape = shape(new);
+    (
+        old_shape.difference(&new_shape).cloned().collect(),
+        new_shape.difference(&old_shape).cloned().collect(),
+    )
+}
+
+fn shape(value: &Value) -> BTreeSet<String> {
+    let mut paths = BTreeSet::new();
+    collect(value, &mut String::new(), &mut paths);
+    paths
+}
+
+fn collect(value: &Value, path: &mut String, paths: &mut BTreeSet<String>) {
+    let type_name = match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    };
+    let display_path = if path.is_empty() { "/" } else { path.as_str() };
+    paths.insert(format!("{}: {}", display_path, type_name));
+
+    let len = path.len();
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                path.push('/');
+                path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+                collect(child, path, paths);
+                path.truncate(len);
+            }
+        }
+        Value::Array(items) => {
+            path.push_str("/*");
+            for item in items {
+                collect(item, path, paths);
+            }
+            path.truncate(len);
+        }
+        _ => {}
+    }
+}
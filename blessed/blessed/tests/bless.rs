@@ -0,0 +1,137 @@
+// `BLESS` stages snapshots with `git add`. Each scenario runs this binary
+// again with the variable set, so it can't leak into other tests, and points
+// it at a scratch git repository instead of this one.
+
+use blessed::{CaseInfo, Outcome};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[blessed::harness]
+fn upper(text: String) -> String {
+    text.to_uppercase()
+}
+
+#[blessed::harness(keyed)]
+fn length(text: String) -> usize {
+    text.len()
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args([
+            "-c",
+            "user.name=blessed",
+            "-c",
+            "user.email=blessed@example.com",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed", args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn case(dir: &Path, name: &'static str, harness: &'static str, params: &'static str) -> CaseInfo {
+    CaseInfo {
+        file: "tests.blessed.json",
+        name,
+        harness,
+        test_fn: name,
+        output_path: Box::leak(format!("blessed/{}.json", name).into_boxed_str()),
+        params,
+        options: "{}",
+        float_epsilon: None,
+        manifest_dir: Box::leak(dir.to_string_lossy().into_owned().into_boxed_str()),
+        manifest_depth: 0,
+        compact_copy: name == "added",
+        module_path: module_path!(),
+        package: "blessed",
+    }
+}
+
+// The scenario itself: runs a new case (with a compact copy), a changed one
+// and a changed keyed entry in `BLESSED_SCRATCH_REPO`, printing how each ended
+#[test]
+fn scratch_cases() {
+    let Some(dir) = std::env::var_os("BLESSED_SCRATCH_REPO").map(PathBuf::from) else {
+        return;
+    };
+    for case in [
+        case(&dir, "added", "upper", r#""new""#),
+        case(&dir, "changed", "upper", r#""changed""#),
+        case(&dir, "counted", "length", r#""abc""#),
+    ] {
+        let outcome = match blessed::run_case(&case).outcome {
+            Outcome::Passed => "passed",
+            _ => "failed",
+        };
+        // libtest's own output can share the line
+        println!("outcome {}: {}", case.name, outcome);
+    }
+}
+
+// Runs the scenario with `BLESS=bless` against a fresh repository with
+// `changed` and the keyed file committed, returning what it printed and what
+// ended up staged
+fn bless(scenario: &str, bless: &str) -> (String, String) {
+    let dir =
+        std::env::temp_dir().join(format!("blessed-bless-{}-{}", scenario, std::process::id()));
+    std::fs::create_dir_all(dir.join("blessed")).unwrap();
+    git(&dir, &["init", "-q"]);
+    std::fs::write(dir.join("blessed/changed.json"), "\"OLD\"").unwrap();
+    std::fs::write(
+        dir.join("blessed/bless__length.inputs.json"),
+        "{\n  \"inputs\": {\n    \"abc\": 2\n  }\n}\n",
+    )
+    .unwrap();
+    git(&dir, &["add", "blessed"]);
+    git(&dir, &["commit", "-q", "-m", "snapshots"]);
+
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "scratch_cases", "--nocapture"])
+        .env("BLESSED_SCRATCH_REPO", &dir)
+        .env("BLESS", bless)
+        .env_remove("BLESSED_UPDATE")
+        .output()
+        .unwrap();
+    let staged = git(&dir, &["diff", "--cached", "--name-status"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    (
+        stdout
+            .lines()
+            .filter_map(|line| line.split_once("outcome ").map(|(_, outcome)| outcome))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        staged,
+    )
+}
+
+#[test]
+fn bless_stages_new_and_changed_snapshots() {
+    let (outcomes, staged) = bless("all", "true");
+    assert_eq!(outcomes, "added: passed\nchanged: passed\ncounted: passed");
+    assert_eq!(
+        staged,
+        "A\tblessed/added.json\n\
+         A\tblessed/added.min.json\n\
+         M\tblessed/bless__length.inputs.json\n\
+         M\tblessed/changed.json\n"
+    );
+}
+
+#[test]
+fn bless_new_stages_only_new_snapshots() {
+    let (outcomes, staged) = bless("new", "new");
+    assert_eq!(outcomes, "added: passed\nchanged: failed\ncounted: failed");
+    assert_eq!(staged, "A\tblessed/added.json\nA\tblessed/added.min.json\n");
+}
+
+#[test]
+fn unset_bless_stages_nothing() {
+    let (outcomes, staged) = bless("none", "0");
+    assert_eq!(outcomes, "added: failed\nchanged: failed\ncounted: failed");
+    assert_eq!(staged, "");
+}
@@ -0,0 +1,70 @@
+// Output hooks apply to every case of a test binary, so they get a binary of
+// their own. The case runs in a scratch git repository.
+
+use blessed::{CaseInfo, Outcome};
+use std::path::Path;
+use std::process::Command;
+
+#[blessed::output_hook]
+fn mask_passwords(text: String) -> String {
+    text.replace("hunter2", "*******")
+}
+
+#[blessed::harness]
+fn login(user: String) -> String {
+    format!("{} logged in with hunter2", user)
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args([
+            "-c",
+            "user.name=blessed",
+            "-c",
+            "user.email=blessed@example.com",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+#[test]
+fn hooked_snapshots_are_still_checked() {
+    let dir = std::env::temp_dir().join(format!("blessed-output-hook-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("blessed")).unwrap();
+    git(&dir, &["init", "-q"]);
+    let case = CaseInfo {
+        file: "tests.blessed.json",
+        name: "alice",
+        harness: "login",
+        test_fn: "blessed_test_tests__alice",
+        output_path: "blessed/alice.json",
+        params: r#""alice""#,
+        options: "{}",
+        float_epsilon: None,
+        manifest_dir: Box::leak(dir.to_string_lossy().into_owned().into_boxed_str()),
+        manifest_depth: 0,
+        compact_copy: false,
+        module_path: module_path!(),
+        package: "blessed",
+    };
+
+    // A hook that keeps the snapshot valid JSON doesn't hide a corrupted
+    // committed snapshot
+    std::fs::write(dir.join("blessed/alice.json"), "{\"alice\"").unwrap();
+    git(&dir, &["add", "blessed/alice.json"]);
+    git(&dir, &["commit", "-q", "-m", "corrupt"]);
+    let outcome = blessed::run_case(&case).outcome;
+    let written = std::fs::read_to_string(dir.join("blessed/alice.json")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(written, "\"alice logged in with *******\"");
+    assert!(
+        matches!(&outcome, Outcome::Failed(message)
+            if message.starts_with("Committed snapshot 'blessed/alice.json' is not valid JSON")),
+        "{:?}",
+        outcome
+    );
+}
@@ -0,0 +1,84 @@
+// Quarantined cases that fail, for checking how their failures are reported.
+// Their outputs are given inline or compared between harnesses, so nothing
+// is written to this crate's snapshots.
+
+use blessed::{CaseInfo, CaseResult, Outcome};
+
+#[blessed::harness]
+fn shout(text: String) -> String {
+    text.to_uppercase()
+}
+
+#[blessed::harness]
+fn shout_trimmed(text: String) -> String {
+    text.trim().to_uppercase()
+}
+
+#[blessed::harness(sensitive)]
+fn login(password: String) -> String {
+    format!("logged in with {}", password)
+}
+
+fn run(harness: &'static str, params: &'static str, options: &'static str) -> CaseResult {
+    blessed::run_case(&CaseInfo {
+        file: "tests.blessed.json",
+        name: "quarantined",
+        harness,
+        test_fn: "blessed_test_tests__quarantined",
+        output_path: "blessed/quarantined.json",
+        params,
+        options,
+        float_epsilon: None,
+        manifest_dir: env!("CARGO_MANIFEST_DIR"),
+        manifest_depth: 2,
+        compact_copy: false,
+        module_path: module_path!(),
+        package: env!("CARGO_PKG_NAME"),
+    })
+}
+
+#[test]
+fn quarantined_failures_pass() {
+    let outcome = run(
+        "shout",
+        r#""ab""#,
+        r#"{"quarantine": true, "expected": "BA"}"#,
+    )
+    .outcome;
+    assert!(
+        matches!(&outcome, Outcome::Quarantined(message) if message.contains("differs from `expected`")),
+        "{:?}",
+        outcome
+    );
+}
+
+#[test]
+fn differing_harnesses_fail_with_a_diff() {
+    let result = run(
+        "shout",
+        r#"" ab ""#,
+        r#"{"quarantine": true, "compare_harnesses": ["shout", "shout_trimmed"]}"#,
+    );
+    assert!(
+        matches!(&result.outcome, Outcome::Quarantined(message)
+            if message.starts_with("harness 'shout_trimmed' differs from harness 'shout' on the same params")),
+        "{:?}",
+        result.outcome
+    );
+    assert!(result.diff.is_some_and(|diff| diff.contains("+\"AB\"")));
+}
+
+#[test]
+fn sensitive_failures_are_redacted() {
+    let outcome = run(
+        "login",
+        r#""hunter2""#,
+        r#"{"quarantine": true, "expected": "logged in with hunter3"}"#,
+    )
+    .outcome;
+    assert!(
+        matches!(&outcome, Outcome::Quarantined(message) if message.contains("[redacted]") && !message.contains("hunter2")),
+        "{:?}",
+        outcome
+    );
+}
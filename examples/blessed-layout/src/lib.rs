@@ -0,0 +1,41 @@
+pub fn shout(text: &str) -> String {
+    text.to_uppercase()
+}
+
+pub fn count_words(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Upper-cases a text.
+    #[blessed::harness]
+    fn shouted(text: String) -> String {
+        shout(&text)
+    }
+
+    /// Counts the words of a text.
+    #[blessed::harness]
+    fn word_count(text: String) -> usize {
+        count_words(&text)
+    }
+
+    blessed::tests!(
+        compact_copies,
+        harness_prefixed_names,
+        per_file_modules,
+        deny_unused_harnesses,
+        deny_stray_snapshots
+    );
+
+    #[test]
+    fn snapshots_are_prefixed_by_harness() {
+        let index = blessed::case_index();
+        let path = index["blessed_test_greetings__hello"]["output_path"]
+            .as_str()
+            .unwrap();
+        assert!(path.ends_with("blessed/shouted__hello.json"), "{}", path);
+    }
+}
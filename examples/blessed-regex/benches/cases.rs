@@ -0,0 +1,11 @@
+// The fixture cases as benchmarks. Only harnesses compiled into this target
+// are benchmarked: the shared ones, not those in the library's test module.
+
+blessed::link!(blessed_shared_harnesses);
+blessed::tests!();
+
+fn main() {
+    let mut criterion = criterion::Criterion::default().configure_from_args();
+    blessed::bench_cases(&mut criterion);
+    criterion.final_summary();
+}
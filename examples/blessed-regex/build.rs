@@ -1,4 +1,5 @@
 fn main() {
     println!("cargo:rerun-if-changed=src/tests/"); // rebuild if a test changes
     println!("cargo:rerun-if-changed=build.rs"); // rebuild if build.rs changes
+    println!("cargo:rerun-if-env-changed=BLESSED_ROOT"); // rediscover tests if their root moves
 }
\ No newline at end of file
@@ -1,10 +1,10 @@
-#[cfg_attr(test, derive(serde::Serialize, Debug))]
+#[cfg_attr(test, derive(serde::Serialize, schemars::JsonSchema, Debug))]
 pub enum Regex {
     Literal(String),
     CharClass(String),
 }
 
-#[cfg_attr(test, derive(serde::Serialize))]
+#[cfg_attr(test, derive(serde::Serialize, schemars::JsonSchema, Debug))]
 pub enum ParseError {
     InvalidRegex(String),
 }
@@ -32,34 +32,81 @@ pub fn match_regex(regex: &Regex, input: &str) -> bool {
     }
 }
 
+pub fn full_match_regex(regex: &Regex, input: &str) -> bool {
+    match regex {
+        Regex::Literal(literal) => input == literal,
+        Regex::CharClass(chars) => {
+            let mut input_chars = input.chars();
+            matches!((input_chars.next(), input_chars.next()), (Some(c), None) if chars.contains(c))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use blessed::{Deserialize, Serialize};
-    use std::collections::HashMap;
+    use std::collections::BTreeMap;
 
     #[derive(Deserialize)]
     struct Case {
         regex: String,
         inputs: Vec<String>,
+        // Match whole inputs rather than finding the regex anywhere in them
+        #[serde(default)]
+        full_match: bool,
     }
 
-    #[derive(Serialize)]
+    #[derive(Serialize, blessed::JsonSchema)]
     struct Output {
         parse_error: Option<ParseError>,
         ast: Option<Regex>,
-        matches: HashMap<String, bool>,
+        // Ordered, so the snapshot doesn't depend on hashing
+        matches: BTreeMap<String, bool>,
     }
 
-    #[blessed::harness]
+    #[derive(Serialize)]
+    struct InputMatch {
+        input: String,
+        matched: bool,
+    }
+
+    #[derive(Serialize)]
+    struct Parsed {
+        regex: String,
+        parsed: Result<Regex, ParseError>,
+    }
+
+    // Cases may omit `inputs` when they only care about parsing.
+    #[blessed::params_hook]
+    fn default_inputs(params: &mut serde_json::Value) {
+        if let Some(params) = params.as_object_mut() {
+            params
+                .entry("inputs")
+                .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+        }
+    }
+
+    /// Parses a regex and matches it against each input.
+    #[blessed::harness(schema)]
     fn parse_compile_match(case: Case) -> Output {
+        for (i, input) in case.inputs.iter().enumerate() {
+            if case.inputs[..i].contains(input) {
+                blessed::warn(format!("input {:?} is listed more than once", input));
+            }
+        }
+        let matcher = if case.full_match {
+            full_match_regex
+        } else {
+            match_regex
+        };
         let parsed = parse_regex(&case.regex);
         match parsed {
             Ok(ast) => {
                 let matches = case
                     .inputs
                     .iter()
-                    .map(|input| (input.clone(), match_regex(&ast, input)))
+                    .map(|input| (input.clone(), matcher(&ast, input)))
                     .collect();
                 Output {
                     ast: Some(ast),
@@ -70,10 +117,451 @@ mod tests {
             Err(e) => Output {
                 ast: None,
                 parse_error: Some(e),
-                matches: HashMap::new(),
+                matches: BTreeMap::new(),
             },
         }
     }
 
-    blessed::tests!();
+    /// Matches a regex against each input, one input per snapshot line.
+    #[blessed::harness(ndjson)]
+    fn match_lines(case: Case) -> Vec<InputMatch> {
+        let Ok(regex) = parse_regex(&case.regex) else {
+            blessed::warn("the regex doesn't parse");
+            return Vec::new();
+        };
+        case.inputs
+            .into_iter()
+            .map(|input| InputMatch {
+                matched: match_regex(&regex, &input),
+                input,
+            })
+            .collect()
+    }
+
+    // Snapshots show `{"Ok": ...}` or `{"Err": {"InvalidRegex": ...}}`
+    #[blessed::harness(format_version = 1, desc = "Parses a regex without matching anything")]
+    fn parse(regex: String) -> Result<Regex, ParseError> {
+        parse_regex(&regex)
+    }
+
+    #[blessed::harness(debug, desc = "Parses a regex and snapshots its Debug output")]
+    fn parse_debug(regex: String) -> Result<Regex, ParseError> {
+        parse_regex(&regex)
+    }
+
+    /// Parses the empty regex, which takes no params.
+    #[blessed::harness]
+    fn empty_regex() -> Result<Regex, ParseError> {
+        parse_regex("")
+    }
+
+    /// Reports which inputs a regex matches, one per line.
+    #[blessed::harness(text)]
+    fn match_report(case: Case) -> String {
+        let ast = match parse_regex(&case.regex) {
+            Ok(ast) => ast,
+            Err(ParseError::InvalidRegex(e)) => return format!("invalid regex: {}", e),
+        };
+        let mut report = format!("regex {}\n", case.regex);
+        for input in &case.inputs {
+            let verdict = if match_regex(&ast, input) { "match" } else { "no match" };
+            report.push_str(&format!("  {:?}: {}\n", input, verdict));
+        }
+        report
+    }
+
+    // Short regexes over a few literal characters and brackets, for `fuzz` cases
+    #[blessed::generator]
+    fn short_regexes(rng: &mut blessed::Rng) -> String {
+        let len = rng.below(5);
+        (0..len).map(|_| *rng.choose(&['a', 'b', '[', ']'])).collect()
+    }
+
+    /// Parses `count` random short regexes, drawn using the case's `seed`.
+    #[blessed::harness]
+    fn parse_random(count: u32) -> Result<Vec<Parsed>, String> {
+        let seed = blessed::current_seed().ok_or("this harness needs a `seed`")?;
+        let mut rng = blessed::Rng::new(seed);
+        let regexes = (0..count).map(|_| short_regexes(&mut rng));
+        Ok(regexes
+            .map(|regex| Parsed {
+                parsed: parse_regex(&regex),
+                regex,
+            })
+            .collect())
+    }
+
+    // `path` is relative to the case's `cwd`
+    #[blessed::harness(desc = "Parses the regex stored in a file")]
+    fn parse_file(path: String) -> Result<Regex, String> {
+        let regex = std::fs::read_to_string(blessed::case_path(&path))
+            .map_err(|e| format!("failed to read {}: {}", path, e))?;
+        parse_regex(&regex).map_err(|e| format!("{:?}", e))
+    }
+
+    /// Parses a regex given as raw bytes, which must be UTF-8.
+    #[blessed::harness]
+    fn parse_bytes(bytes: Vec<u8>) -> Result<Regex, String> {
+        let regex = String::from_utf8(bytes).map_err(|e| e.to_string())?;
+        parse_regex(&regex).map_err(|e| format!("{:?}", e))
+    }
+
+    /// Parses each line of a list of regexes, snapshotted along with the list.
+    #[blessed::harness(normalize_newlines)]
+    fn parse_lines(list: String) -> (String, Vec<Result<Regex, ParseError>>) {
+        let parsed = list.lines().map(parse_regex).collect();
+        (list, parsed)
+    }
+
+    /// Parses a regex given either as a string or as `{"regex": ...}`.
+    #[blessed::harness(raw_input)]
+    fn parse_loose(params: serde_json::Value) -> Result<Regex, String> {
+        let regex = match &params {
+            serde_json::Value::String(regex) => regex,
+            other => other["regex"]
+                .as_str()
+                .ok_or_else(|| format!("no regex in {}", other))?,
+        };
+        parse_regex(regex).map_err(|e| format!("{:?}", e))
+    }
+
+    /// Parses a regex, recording every case in one table keyed by regex.
+    #[blessed::harness(keyed)]
+    fn parse_table(regex: String) -> Result<Regex, ParseError> {
+        parse_regex(&regex)
+    }
+
+    /// Parses a regex after trimming surrounding whitespace.
+    #[blessed::harness]
+    fn parse_trimmed(regex: String) -> Result<Regex, ParseError> {
+        parse_regex(regex.trim())
+    }
+
+    // Shared by every case, built on first use
+    struct Dictionary(Vec<&'static str>);
+
+    #[blessed::context]
+    fn dictionary() -> Dictionary {
+        Dictionary(vec!["apple", "banana", "cherry", "date", "fig"])
+    }
+
+    /// Lists the dictionary words a regex matches.
+    #[blessed::harness]
+    fn match_dictionary(regex: String) -> Result<Vec<&'static str>, ParseError> {
+        let regex = parse_regex(&regex)?;
+        let words = &blessed::context::<Dictionary>().0;
+        Ok(words
+            .iter()
+            .copied()
+            .filter(|word| match_regex(&regex, word))
+            .collect())
+    }
+
+    /// Runs cargo with the given arguments, in the case's `cwd`.
+    #[blessed::harness]
+    fn cargo(args: Vec<String>) -> Result<blessed::CommandOutput, String> {
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        blessed::run_command(std::process::Command::new(cargo).args(args))
+    }
+
+    blessed::golden!(
+        regex_validity,
+        |regex: String| parse_regex(&regex).is_ok(),
+        r#"{ "literal": "abc", "unbalanced": "[a" }"#
+    );
+
+    blessed::link!(blessed_shared_harnesses);
+    blessed::tests!(
+        deny_unused_harnesses,
+        check_isolation,
+        deny_stray_snapshots,
+        gitattributes,
+        require_committed
+    );
+
+    #[test]
+    fn cases_are_listed() {
+        let cases = blessed::list_cases();
+        assert!(cases.contains(&(
+            "src/tests/tests.blessed.json",
+            "happy",
+            "parse_compile_match"
+        )));
+    }
+
+    #[test]
+    fn case_index_maps_tests_to_snapshots() {
+        let index = blessed::case_index();
+        assert_eq!(
+            index["blessed_test_tests__happy"]["output_path"],
+            "examples/blessed-regex/blessed/happy.json"
+        );
+    }
+
+    #[test]
+    fn cases_can_be_queried() {
+        let loose: Vec<_> = blessed::cases()
+            .filter(|case| case.harness == "parse_loose")
+            .map(|case| (case.name, case.params, case.output_path))
+            .collect();
+        assert_eq!(
+            loose,
+            [
+                (
+                    "loose_object",
+                    serde_json::json!({ "regex": "[b]" }),
+                    "examples/blessed-regex/blessed/loose_object.json".to_string()
+                ),
+                (
+                    "loose_string",
+                    serde_json::json!("ab"),
+                    "examples/blessed-regex/blessed/loose_string.json".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn cases_are_reported_as_markdown() {
+        let cases = blessed::cases().filter(|case| case.name.starts_with("loose_"));
+        assert_eq!(
+            blessed::markdown_report(cases),
+            r#"# Blessed cases
+
+## `src/tests/parsing.blessed.json`
+
+### `loose_object`
+
+Harness `parse_loose`: Parses a regex given either as a string or as `{"regex": ...}`.
+
+```json
+{
+  "Ok": {
+    "CharClass": "b"
+  }
+}
+```
+
+### `loose_string`
+
+Harness `parse_loose`: Parses a regex given either as a string or as `{"regex": ...}`.
+
+```json
+{
+  "Ok": {
+    "Literal": "ab"
+  }
+}
+```
+"#
+        );
+    }
+
+    #[test]
+    fn snapshots_can_be_checked_by_hand() {
+        blessed::assert_no_changes("parse", "[ab]", "manual_class");
+    }
+
+    #[test]
+    fn test_names_can_be_computed() {
+        let index = blessed::case_index();
+        let cases = blessed::list_cases().into_iter();
+        for (file, name, _) in cases.filter(|(file, _, _)| file.ends_with(".blessed.json")) {
+            let file_name = file.rsplit('/').next().unwrap();
+            let test_fn = blessed::test_fn_name(file_name, name);
+            assert_eq!(index[&test_fn]["name"], name, "{}", test_fn);
+        }
+    }
+
+    #[test]
+    fn failures_say_how_to_rerun_the_case() {
+        let case = inventory::iter::<blessed::CaseInfo>
+            .into_iter()
+            .find(|case| case.test_fn == "blessed_test_tests__happy")
+            .unwrap();
+        assert_eq!(case.test_path(), "tests::blessed_test_tests__happy");
+        assert!(case.reproduce_command().ends_with(
+            "cargo test -p blessed-regex -- --exact tests::blessed_test_tests__happy"
+        ));
+    }
+
+    #[test]
+    fn enums_can_be_retagged() {
+        let output = blessed::retag_enums(
+            (
+                parse_regex("[ab]"),
+                parse_regex("["),
+                Some(Regex::Literal("x".to_string())),
+            ),
+            "type",
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            serde_json::json!([
+                { "type": "Ok", "content": { "type": "CharClass", "content": "ab" } },
+                { "type": "Err", "content": { "type": "InvalidRegex", "content": "Mismatched or misplaced brackets" } },
+                { "type": "Literal", "content": "x" },
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_snapshots_cover_regex_variants() {
+        let coverage = blessed::variant_coverage::<Result<Regex, ParseError>>("parse").unwrap();
+        assert_eq!(
+            coverage.to_string(),
+            "ParseError: 1/1 variants seen\n\
+             Regex: 2/2 variants seen\n\
+             Result_of_Regex_or_ParseError: 2/2 variants seen\n"
+        );
+    }
+
+    #[test]
+    fn readonly_runs_check_in_memory() {
+        // A separate run of this test binary, so the variable can't leak into other tests
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "tests::blessed_test_tests__happy"])
+            .env("BLESS_READONLY", "1")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("1 passed"), "{}", stdout);
+    }
+
+    #[test]
+    fn staged_snapshots_are_compared_exactly() {
+        let git_root = concat!(env!("CARGO_MANIFEST_DIR"), "/../..");
+        let path = "examples/blessed-regex/blessed/digits.json";
+        // Not the working tree copy, which the digits case may be rewriting
+        let staged = std::process::Command::new("git")
+            .args(["show", &format!(":{}", path)])
+            .current_dir(git_root)
+            .output()
+            .unwrap()
+            .stdout;
+        let staged = String::from_utf8(staged).unwrap();
+        assert_eq!(
+            blessed::check_against_ref(git_root, blessed::STAGED_REF, path, &staged),
+            Ok(())
+        );
+        let stale = blessed::check_against_ref(git_root, blessed::STAGED_REF, path, "{}\n");
+        assert!(
+            stale
+                .as_ref()
+                .is_err_and(|e| e.starts_with(&format!("File '{}' differs from its staged version:", path))),
+            "{:?}",
+            stale
+        );
+    }
+
+    #[test]
+    fn output_schema_violations_name_the_path() {
+        let output = serde_json::json!({ "parse_error": null, "ast": 3, "matches": {} });
+        let error = blessed::validate_output::<Output>(&output).unwrap_err();
+        assert!(error.contains("at /ast:"), "{}", error);
+        let output = serde_json::json!({ "parse_error": null, "ast": null, "matches": {} });
+        assert_eq!(blessed::validate_output::<Output>(&output), Ok(()));
+    }
+
+    #[test]
+    fn ndjson_snapshots_need_a_sequence() {
+        let harness = blessed::find_harness("match_lines").unwrap();
+        let options = blessed::CaseOptions::default();
+        let render = |output| blessed::render_snapshot(harness, Ok(output), Vec::new(), &options);
+        let lines = render(serde_json::json!([{ "b": 1, "a": 2 }, 3])).unwrap();
+        assert_eq!(lines, "{\"a\":2,\"b\":1}\n3\n");
+        let error = render(serde_json::json!({ "a": 1 })).unwrap_err();
+        assert!(error.contains("must be a sequence, not an object"), "{}", error);
+    }
+
+    #[test]
+    fn harnesses_are_described() {
+        assert_eq!(
+            blessed::list_harnesses(),
+            [
+                ("cargo", "Runs cargo with the given arguments, in the case's `cwd`."),
+                (
+                    "echo",
+                    "Snapshots its params unchanged, after any params hooks and directives."
+                ),
+                ("empty_regex", "Parses the empty regex, which takes no params."),
+                ("match_dictionary", "Lists the dictionary words a regex matches."),
+                ("match_lines", "Matches a regex against each input, one input per snapshot line."),
+                (
+                    "match_report",
+                    "Reports which inputs a regex matches, one per line."
+                ),
+                ("parse", "Parses a regex without matching anything"),
+                ("parse_bytes", "Parses a regex given as raw bytes, which must be UTF-8."),
+                (
+                    "parse_compile_match",
+                    "Parses a regex and matches it against each input."
+                ),
+                (
+                    "parse_debug",
+                    "Parses a regex and snapshots its Debug output"
+                ),
+                ("parse_file", "Parses the regex stored in a file"),
+                (
+                    "parse_lines",
+                    "Parses each line of a list of regexes, snapshotted along with the list."
+                ),
+                (
+                    "parse_loose",
+                    "Parses a regex given either as a string or as `{\"regex\": ...}`."
+                ),
+                (
+                    "parse_random",
+                    "Parses `count` random short regexes, drawn using the case's `seed`."
+                ),
+                (
+                    "parse_table",
+                    "Parses a regex, recording every case in one table keyed by regex."
+                ),
+                (
+                    "parse_trimmed",
+                    "Parses a regex after trimming surrounding whitespace."
+                ),
+                ("regex_validity", "golden!(regex_validity)"),
+            ]
+        );
+    }
+
+    // A property over generated regexes; under BLESS_RECORD=1 any
+    // counterexample would become a `parse` case
+    #[test]
+    fn generated_regexes_parse_or_report_brackets() {
+        let mut rng = blessed::Rng::new(3);
+        for _ in 0..200 {
+            let regex = short_regexes(&mut rng);
+            let holds = match parse_regex(&regex) {
+                Ok(_) => true,
+                Err(ParseError::InvalidRegex(message)) => message.contains("brackets"),
+            };
+            if !holds {
+                let recorded = blessed::record_case("parse", &regex).unwrap();
+                panic!("property fails for {:?} (recorded as {:?})", regex, recorded);
+            }
+        }
+    }
+
+    #[test]
+    fn harness_signatures_are_recorded() {
+        let signature = |name| blessed::find_harness(name).unwrap().signature();
+        assert_eq!(signature("parse"), "String -> Result<Regex, ParseError>");
+        assert_eq!(signature("empty_regex"), "() -> Result<Regex, ParseError>");
+        assert_eq!(signature("parse_compile_match"), "Case -> Output");
+        assert_eq!(signature("regex_validity"), "String -> _");
+    }
+
+    #[test]
+    fn harnesses_resolve_by_qualified_name() {
+        for name in ["parse", "tests::parse", "blessed_regex::tests::parse"] {
+            let harness = blessed::find_harness(name).unwrap();
+            assert_eq!(harness.qualified_name(), "blessed_regex::tests::parse");
+        }
+        assert!(blessed::find_harness("other::parse").is_err());
+    }
 }
@@ -0,0 +1,5 @@
+/// Snapshots its params unchanged, after any params hooks and directives.
+#[blessed::harness]
+fn echo(params: serde_json::Value) -> serde_json::Value {
+    params
+}